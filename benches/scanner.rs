@@ -0,0 +1,56 @@
+// Scanning a 4 MiB script built from `SNIPPET`, measured with `cargo bench --bench scanner`.
+// To compare against an older scanner, check it out in a worktree, copy this file into its
+// `benches/` and run the same command there; criterion reports the change against the last run.
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use fast_frox::scanner::{Scanner, TokenType};
+
+const SNIPPET: &str = r#"
+// compute some numbers
+fun fibonacci(n) {
+    if (n <= 1) return n; /* base case */
+    return fibonacci(n - 2) + fibonacci(n - 1);
+}
+
+class Counter {
+    init() { this.count = 0; }
+    increment() { this.count = this.count + 1.5; return this; }
+}
+
+var counter = Counter();
+while (counter.count < 100 and !false) {
+    counter.increment();
+    print "count: " + counter.count;
+}
+"#;
+
+fn generated_script() -> String {
+    SNIPPET.repeat(4 * 1024 * 1024 / SNIPPET.len())
+}
+
+fn count_tokens(mut scanner: Scanner) -> usize {
+    let mut count = 0;
+    loop {
+        let token = scanner.scan_token().unwrap();
+        if token.tpe == TokenType::Eof {
+            return count;
+        }
+        count += 1;
+    }
+}
+
+fn scanner_benchmark(c: &mut Criterion) {
+    let source = generated_script();
+    let mut group = c.benchmark_group("scanner");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+    group.sample_size(20);
+    group.bench_function("tokens", |b| {
+        b.iter(|| count_tokens(Scanner::new(black_box(&source))))
+    });
+    group.bench_function("tokens_with_trivia", |b| {
+        b.iter(|| count_tokens(Scanner::with_trivia(black_box(&source))))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, scanner_benchmark);
+criterion_main!(benches);
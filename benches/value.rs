@@ -0,0 +1,55 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use fast_frox::value::Value;
+
+// Both value layouts share this bench, compare them with
+//   cargo bench --bench value
+//   cargo bench --bench value --features nan-boxing
+// which report as `value/enum` and `value/nan_boxed` respectively.
+const VALUES: usize = 1 << 20;
+
+fn numbers() -> Vec<Value> {
+    (0..VALUES).map(|i| Value::Number(i as f64 * 0.5)).collect()
+}
+
+fn value_benchmark(c: &mut Criterion) {
+    let layout = if cfg!(feature = "nan-boxing") {
+        "nan_boxed"
+    } else {
+        "enum"
+    };
+
+    let values = numbers();
+    let mut group = c.benchmark_group(format!("value/{}", layout));
+    group.throughput(Throughput::Elements(VALUES as u64));
+    group.bench_function("sum", |b| {
+        b.iter(|| {
+            values
+                .iter()
+                .fold(Value::Number(0.0), |acc, value| (acc + *value).unwrap())
+        })
+    });
+    group.bench_function("multiply_add", |b| {
+        b.iter(|| {
+            values.windows(2).fold(Value::Number(0.0), |acc, pair| {
+                (acc + (pair[0] * pair[1]).unwrap()).unwrap()
+            })
+        })
+    });
+    group.bench_function("copy", |b| {
+        b.iter(|| black_box(values.clone()))
+    });
+    group.bench_function("is_number", |b| {
+        let mixed = (0..VALUES)
+            .map(|i| match i % 3 {
+                0 => Value::Number(i as f64),
+                1 => Value::Boolean(i % 2 == 0),
+                _ => Value::Nil,
+            })
+            .collect::<Vec<_>>();
+        b.iter(|| mixed.iter().filter(|value| value.is_number()).count())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, value_benchmark);
+criterion_main!(benches);
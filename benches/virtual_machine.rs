@@ -0,0 +1,51 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use fast_frox::virtual_machine::VirtualMachine;
+
+// Runs precompiled chunks with `cargo bench --bench virtual_machine`. Verified
+// chunks are dispatched with `OpCode::from_byte_unchecked` and unchecked
+// constant loads. Swapping those for `OpCode::try_from` and indexing, then
+// running with `--baseline` against a saved unchecked run, gave on my machine:
+//   arithmetic  checked ~132 us, unchecked ~87 us
+//   negation    checked  ~64 us, unchecked ~43 us
+//
+// The inline number paths of `binary_operation` and `op_negate` make no
+// measurable difference: always going through the `Value` operators stayed
+// within the noise of these benches (~88-104 us and ~33-39 us).
+const TERMS: usize = 10_000;
+
+// `1 + 2 * 3 - 4 / 5 + ...`, exercising every arithmetic instruction
+fn arithmetic_kernel() -> String {
+    let operators = [" + ", " * ", " - ", " / "];
+    let mut source = String::from("1");
+    for i in 0..TERMS {
+        source.push_str(operators[i % operators.len()]);
+        source.push_str(&((i % 97) + 1).to_string());
+    }
+    source
+}
+
+// `----------0 + ----------1 + ...`, exercising negation
+fn negation_kernel() -> String {
+    (0..TERMS / 10)
+        .map(|i| format!("{}{}", "-".repeat(10), i))
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+fn virtual_machine_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("virtual_machine");
+    for (name, source) in [
+        ("arithmetic", arithmetic_kernel()),
+        ("negation", negation_kernel()),
+    ] {
+        let mut vm = VirtualMachine::new(false);
+        let chunk = vm.compile(&source).unwrap();
+
+        group.throughput(Throughput::Elements(TERMS as u64));
+        group.bench_function(name, |b| b.iter(|| vm.execute(&chunk).unwrap()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, virtual_machine_benchmark);
+criterion_main!(benches);
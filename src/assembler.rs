@@ -0,0 +1,383 @@
+use miette::{NamedSource, Result};
+
+use crate::{chunk::Chunk, error::CompileError, op_code::OpCode, value::Value};
+
+/// Assembles the textual format printed by the disassembler back into a [`Chunk`].
+///
+/// Every line holds one of
+///
+/// - an instruction `OP_CONSTANT 0 1.5` or `OP_GET_GLOBAL 0 name`, where the
+///   constant or name index is optional,
+///   optionally prefixed by the offset and line columns of a listing (`0000    1 `),
+/// - a `.line <line>[:<column>]` directive attributing the following instructions.
+///
+/// `;` starts a comment and `== name ==` headers are skipped. The chunk is not
+/// verified, which allows assembling invalid code for testing the verifier.
+pub fn assemble(source: &str) -> Result<Chunk> {
+    Assembler::new(source).assemble()
+}
+
+struct Assembler<'a> {
+    source: &'a str,
+    chunk: Chunk,
+    line: usize,
+    column: usize,
+}
+
+/// A whitespace separated word of the source and its byte offset.
+#[derive(Clone, Copy)]
+struct Word<'a> {
+    text: &'a str,
+    start: usize,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(source: &'a str) -> Self {
+        Assembler {
+            source,
+            chunk: Chunk::new(),
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn assemble(mut self) -> Result<Chunk> {
+        let mut line_start = 0;
+        for line in self.source.split_inclusive('\n') {
+            let content = line.split(';').next().unwrap_or_default();
+            let words = words(content, line_start);
+            line_start += line.len();
+
+            match words.as_slice() {
+                [] => {}
+                [first, ..] if first.text.starts_with("==") => {}
+                [directive, operands @ ..] if directive.text.starts_with('.') => {
+                    self.directive(*directive, operands)?
+                }
+                _ => self.instruction(&words)?,
+            }
+        }
+        Ok(self.chunk)
+    }
+
+    fn directive(&mut self, directive: Word, operands: &[Word]) -> Result<()> {
+        match (directive.text, operands) {
+            (".line", [position]) => {
+                let (line, column) = match position.text.split_once(':') {
+                    Some((line, column)) => (line, column),
+                    None => (position.text, "1"),
+                };
+                match (line.parse(), column.parse()) {
+                    (Ok(line), Ok(column)) => {
+                        self.line = line;
+                        self.column = column;
+                        Ok(())
+                    }
+                    _ => Err(self.error(*position, "Expected a position like `12` or `12:5`")),
+                }
+            }
+            (".line", _) => Err(self.error(directive, "Expected a single position after `.line`")),
+            _ => Err(self.error(directive, &format!("Unknown directive `{}`", directive.text))),
+        }
+    }
+
+    fn instruction(&mut self, words: &[Word]) -> Result<()> {
+        let words = self.skip_listing_columns(words)?;
+        let (mnemonic, operands) = match words.split_first() {
+            Some(split) => split,
+            None => return Ok(()),
+        };
+        let op_code = OpCode::from_mnemonic(mnemonic.text)
+            .ok_or_else(|| self.error(*mnemonic, &format!("Unknown instruction `{}`", mnemonic.text)))?;
+
+        match op_code {
+            OpCode::OpConstant | OpCode::OpConstantLong => {
+                let index = self.constant(*mnemonic, operands)?;
+                let operand = (index as u32).to_le_bytes();
+                let operand = match op_code {
+                    OpCode::OpConstant if index <= u8::MAX as usize => &operand[..1],
+                    OpCode::OpConstant => {
+                        return Err(self.error(
+                            operands[0],
+                            "Constant index does not fit into a byte, use OP_CONSTANT_LONG",
+                        ))
+                    }
+                    _ if index < 1 << 24 => &operand[..3],
+                    _ => return Err(self.error(operands[0], "Constant index exceeds 24 bits")),
+                };
+                self.emit(op_code as u8);
+                for byte in operand {
+                    self.emit(*byte);
+                }
+            }
+            OpCode::OpGetGlobal => {
+                let index = self.name(*mnemonic, operands)?;
+                let index = u8::try_from(index).map_err(|_| self.error(operands[0], "Name index exceeds a byte"))?;
+                self.emit(op_code as u8);
+                self.emit(index);
+            }
+            OpCode::OpCall => {
+                let count = match operands {
+                    [count] => count.text.parse::<u8>().ok(),
+                    _ => None,
+                };
+                let count = count.ok_or_else(|| self.error(*mnemonic, "Expected an argument count like `2`"))?;
+                self.emit(op_code as u8);
+                self.emit(count);
+            }
+            _ => {
+                if let Some(operand) = operands.first() {
+                    return Err(self.error(
+                        *operand,
+                        &format!("{} does not take operands", op_code.mnemonic()),
+                    ));
+                }
+                self.emit(op_code as u8);
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the offset and line columns of disassembly listings, picking up the line.
+    fn skip_listing_columns<'w>(&mut self, words: &'w [Word<'w>]) -> Result<&'w [Word<'w>]> {
+        match words {
+            [offset, line, rest @ ..] if is_number(offset.text) => {
+                if line.text != "|" {
+                    self.line = line
+                        .text
+                        .parse()
+                        .map_err(|_| self.error(*line, "Expected a line number or `|`"))?;
+                    self.column = 1;
+                }
+                Ok(rest)
+            }
+            _ => Ok(words),
+        }
+    }
+
+    /// Resolves the operands `[index] value` to an index into the constant pool.
+    fn constant(&mut self, mnemonic: Word, operands: &[Word]) -> Result<usize> {
+        let (index, value) = match operands {
+            [value] => (None, *value),
+            [index, value] => (Some(*index), *value),
+            _ => return Err(self.error(mnemonic, "Expected a constant like `0 1.5` or `1.5`")),
+        };
+        let value = parse_value(value.text)
+            .ok_or_else(|| self.error(value, &format!("Invalid constant `{}`", value.text)))?;
+
+        let index = match index {
+            None => return Ok(self.chunk.add_constant(value)),
+            Some(index) => index,
+        };
+        let position = index
+            .text
+            .parse::<usize>()
+            .map_err(|_| self.error(index, "Expected a constant index"))?;
+        match self.chunk.constants.get(position) {
+            Some(existing) if same_value(*existing, value) => Ok(position),
+            Some(existing) => Err(self.error(
+                index,
+                &format!("Constant {} is already defined as {}", position, existing),
+            )),
+            None if position == self.chunk.constants.len() => Ok(self.chunk.add_constant(value)),
+            None => Err(self.error(
+                index,
+                &format!("Expected the next constant index {}", self.chunk.constants.len()),
+            )),
+        }
+    }
+
+    /// Resolves the operands `[index] name` to an index into the name table.
+    fn name(&mut self, mnemonic: Word, operands: &[Word]) -> Result<usize> {
+        let (index, name) = match operands {
+            [name] => (None, *name),
+            [index, name] => (Some(*index), *name),
+            _ => return Err(self.error(mnemonic, "Expected a name like `0 clock` or `clock`")),
+        };
+
+        let index = match index {
+            None => return Ok(self.chunk.add_name(name.text)),
+            Some(index) => index,
+        };
+        let position = index
+            .text
+            .parse::<usize>()
+            .map_err(|_| self.error(index, "Expected a name index"))?;
+        match self.chunk.names.get(position) {
+            Some(existing) if existing == name.text => Ok(position),
+            Some(existing) => Err(self.error(
+                index,
+                &format!("Name {} is already defined as {}", position, existing),
+            )),
+            None if position == self.chunk.names.len() => {
+                self.chunk.names.push(name.text.to_owned());
+                Ok(position)
+            }
+            None => Err(self.error(
+                index,
+                &format!("Expected the next name index {}", self.chunk.names.len()),
+            )),
+        }
+    }
+
+    fn emit(&mut self, byte: u8) {
+        self.chunk.write_chunk(byte, self.line, self.column);
+    }
+
+    fn error(&self, word: Word, msg: &str) -> miette::Report {
+        CompileError {
+            msg: msg.to_owned(),
+            src: NamedSource::new("", self.source.to_owned()),
+            span: (word.start, word.text.len()).into(),
+        }
+        .into()
+    }
+}
+
+fn words(line: &str, line_start: usize) -> Vec<Word<'_>> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (index, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (c.is_whitespace(), start) {
+            (true, Some(word_start)) => {
+                words.push(Word {
+                    text: &line[word_start..index],
+                    start: line_start + word_start,
+                });
+                start = None;
+            }
+            (false, None) => start = Some(index),
+            _ => {}
+        }
+    }
+    words
+}
+
+fn is_number(text: &str) -> bool {
+    !text.is_empty() && text.bytes().all(|byte| byte.is_ascii_digit())
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    match text {
+        "nil" => Some(Value::Nil),
+        "true" => Some(Value::Boolean(true)),
+        "false" => Some(Value::Boolean(false)),
+        _ => text.parse().ok().map(Value::Number),
+    }
+}
+
+fn same_value(lhs: Value, rhs: Value) -> bool {
+    match (lhs.number(), rhs.number()) {
+        (Some(lhs), Some(rhs)) => lhs.to_bits() == rhs.to_bits() || (lhs.is_nan() && rhs.is_nan()),
+        (None, None) => lhs.boolean() == rhs.boolean() && lhs.is_nil() == rhs.is_nil(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        debug::ChunkDebug,
+        virtual_machine::{
+            testing::{assert_same_chunk, compile},
+            VirtualMachine,
+        },
+    };
+
+    #[test]
+    fn should_assemble_instructions() {
+        let chunk = assemble(
+            "
+            ; -(1.5 + 2)
+            .line 1:4
+            OP_CONSTANT 1.5
+            OP_CONSTANT 2
+            OP_ADD
+            OP_NEGATE
+            .line 1:11
+            OP_RETURN
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(chunk.code, compile("-(1.5 + 2)").code);
+        assert_eq!(chunk.get_position(0), Some((1, 4)));
+        assert_eq!(chunk.get_position(6), Some((1, 11)));
+    }
+
+    #[test]
+    fn should_assemble_disassembly_listing() {
+        let expected = compile("1.5 * -2\n  / true + nil");
+        let chunk = assemble(
+            "== code ==
+            0000    1 OP_CONSTANT    0 1.5
+            0002    | OP_CONSTANT    1 2
+            0004    | OP_NEGATE
+            0005    | OP_MULTIPLY
+            0006    2 OP_TRUE
+            0007    | OP_DIVIDE
+            0008    | OP_NIL
+            0009    | OP_ADD
+            0010    | OP_RETURN
+            == code ==",
+        )
+        .unwrap();
+
+        assert_same_chunk(&chunk, &expected, false);
+    }
+
+    #[test]
+    fn should_reassemble_disassembly() {
+        let long_constants = (0..300).map(|i| i.to_string()).collect::<Vec<_>>().join(" + ");
+        for source in [
+            "-(1.5 +\n 2) * nil / true - false",
+            "0.25 / 0 - 1.0 * 1234567.125",
+            "f(x, g(), f)",
+            &long_constants,
+        ] {
+            let chunk = compile(source);
+            let listing = chunk.disassemble("code").to_string();
+
+            assert_same_chunk(&assemble(&listing).unwrap(), &chunk, false);
+        }
+    }
+
+    #[test]
+    fn should_assemble_long_constants() {
+        let chunk = assemble("OP_CONSTANT_LONG 0 nil\nOP_CONSTANT 0 nil\nOP_RETURN").unwrap();
+
+        assert_eq!(chunk.code, vec![10, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(chunk.constants.len(), 1);
+    }
+
+    #[test]
+    fn should_execute_assembled_chunk() {
+        let chunk = assemble(".line 3:7\nOP_TRUE\nOP_NEGATE\nOP_RETURN").unwrap();
+        let mut vm = VirtualMachine::new(false);
+
+        let error = vm.execute(&chunk).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unable to negate true, operand must be a number\n[1] 3:7"
+        );
+    }
+
+    #[test]
+    fn should_report_invalid_assembly() {
+        let error = |source| assemble(source).err().unwrap().to_string();
+
+        assert_eq!(error("OP_JUMP end"), "Unknown instruction `OP_JUMP`");
+        assert_eq!(error("OP_ADD 1"), "OP_ADD does not take operands");
+        assert_eq!(error("OP_CONSTANT 1 nil"), "Expected the next constant index 0");
+        assert_eq!(
+            error("OP_CONSTANT 0 nil\nOP_CONSTANT 0 true"),
+            "Constant 0 is already defined as nil"
+        );
+        assert_eq!(error("OP_CONSTANT 0 one"), "Invalid constant `one`");
+        assert_eq!(error("OP_GET_GLOBAL 0 f\nOP_GET_GLOBAL 0 g"), "Name 0 is already defined as f");
+        assert_eq!(error("OP_CALL"), "Expected an argument count like `2`");
+        assert_eq!(error(".line"), "Expected a single position after `.line`");
+        assert_eq!(error(".file x"), "Unknown directive `.file`");
+    }
+}
@@ -0,0 +1,108 @@
+use miette::SourceSpan;
+
+use crate::scanner::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Byte range of a node in the source, together with the positions of its first and last token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub length: usize,
+    pub begin: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Literal(Literal),
+    Grouping(Box<Expr>),
+    Unary {
+        operator: UnaryOperator,
+        operand: Box<Expr>,
+    },
+    Binary {
+        operator: BinaryOperator,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Variable(String),
+    Call {
+        callee: Box<Expr>,
+        arguments: Vec<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Literal {
+    Number(f64),
+    Boolean(bool),
+    Nil,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOperator {
+    Negate,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Expr { kind, span }
+    }
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            length: other.start + other.length - self.start,
+            begin: self.begin,
+            end: other.end,
+        }
+    }
+}
+
+impl From<Token> for Position {
+    fn from(value: Token) -> Self {
+        Position {
+            line: value.line,
+            column: value.column,
+        }
+    }
+}
+
+impl From<Token> for Span {
+    fn from(value: Token) -> Self {
+        Span {
+            start: value.start,
+            length: value.length,
+            begin: value.into(),
+            end: value.into(),
+        }
+    }
+}
+
+impl From<Span> for SourceSpan {
+    fn from(value: Span) -> Self {
+        SourceSpan::new(value.start.into(), value.length.into())
+    }
+}
@@ -1,10 +1,20 @@
-use crate::{debug::ChunkDebug, op_code::OpCode, value::Value, InstructionSize};
+use crate::{debug::{ChunkDebug, Disassembly, Instruction}, error::VerifierError, op_code::OpCode, value::Value, verifier, InstructionSize};
+
+mod serialization;
 
 #[derive(Default)]
 pub struct Chunk {
     pub(crate) code: Vec<u8>,
     pub(crate) constants: Vec<Value>,
+    /// Identifiers referred to by instructions such as `OpGetGlobal`.
+    pub(crate) names: Vec<String>,
+    /// Run-length encoded lines, consecutive instructions mostly share one.
     lines: Vec<Line>,
+    /// Column of every byte of code, which rarely repeats between instructions.
+    columns: Vec<u32>,
+    verified: bool,
+    /// Stack depth the chunk needs, known once verified.
+    max_stack_depth: usize,
 }
 
 #[derive(Debug)]
@@ -18,84 +28,161 @@ impl Chunk {
         Chunk {
             code: Vec::new(),
             constants: Vec::new(),
+            names: Vec::new(),
             lines: Vec::new(),
+            columns: Vec::new(),
+            verified: false,
+            max_stack_depth: 0,
         }
     }
 
-    pub fn write_chunk(&mut self, chunk: u8, line: usize) {
+    pub fn write_chunk(&mut self, chunk: u8, line: usize, column: usize) {
         self.code.push(chunk);
-        self.set_line(line);
+        self.set_line(line, column);
+        self.verified = false;
     }
 
-    pub fn add_constant(&mut self, value: Value) -> u8 {
+    pub fn add_constant(&mut self, value: Value) -> usize {
         self.constants.push(value);
-        (self.constants.len() - 1) as u8
+        self.verified = false;
+        self.constants.len() - 1
     }
 
-    fn set_line(&mut self, line: usize) {
-        if !self.lines.is_empty() && self.lines.last().unwrap().line == line {
-            let last = self.lines.last_mut().unwrap();
-            last.length += 1;
-            return;
+    /// Index of `name` in the name table, adding it if missing.
+    pub fn add_name(&mut self, name: &str) -> usize {
+        if let Some(index) = self.names.iter().position(|existing| existing == name) {
+            return index;
+        }
+        self.names.push(name.to_owned());
+        self.verified = false;
+        self.names.len() - 1
+    }
+
+    /// Runs the [verifier](crate::verifier) once, so that executing the chunk skips it.
+    pub fn verify(&mut self) -> Result<(), VerifierError> {
+        self.max_stack_depth = verifier::verify(self)?;
+        self.verified = true;
+        Ok(())
+    }
+
+    pub fn is_verified(&self) -> bool {
+        self.verified
+    }
+
+    pub(crate) fn max_stack_depth(&self) -> usize {
+        self.max_stack_depth
+    }
+
+    fn set_line(&mut self, line: usize, column: usize) {
+        self.columns.push(u32::try_from(column).unwrap_or(u32::MAX));
+        if let Some(last) = self.lines.last_mut() {
+            if last.line == line && last.length < u16::MAX {
+                last.length += 1;
+                return;
+            }
         }
         self.lines.push(Line { line, length: 1 });
     }
 
-    pub(crate) fn get_line(&self, offset: usize) -> usize {
+    /// Line and column of the code at `offset`, `None` past the end of the code.
+    pub(crate) fn get_position(&self, offset: usize) -> Option<(usize, usize)> {
+        let column = *self.columns.get(offset)? as usize;
         let mut length = 0;
-        let mut last_line = self.lines.first().unwrap();
         for line in &self.lines {
+            length += line.length as usize;
             if offset < length {
-                return last_line.line;
+                return Some((line.line, column));
             }
-            length += line.length as usize;
-            last_line = line;
-        }
-
-        if offset < length {
-            last_line.line
-        } else {
-            panic!()
         }
+        None
     }
 }
 
-impl ChunkDebug<OpCode> for Chunk {
-    fn disassemblee_chunk(&self, name: &str) {
-        println!("== {} ==", name);
+/// Decodes the little-endian 24 bit operand of a long instruction.
+pub(crate) fn read_long_operand(bytes: &[u8]) -> usize {
+    bytes[0] as usize | (bytes[1] as usize) << 8 | (bytes[2] as usize) << 16
+}
+
+impl ChunkDebug for Chunk {
+    fn disassemble(&self, name: &str) -> Disassembly {
+        let mut instructions = Vec::new();
         let mut offset = 0;
         while offset < self.code.len() {
-            offset += self.disassemble_instruction(offset);
+            let instruction = self.disassemble_instruction(offset);
+            offset += instruction.op_code.map_or(1, |op_code| op_code.size());
+            instructions.push(instruction);
         }
-
-        println!("== {} ==", name);
-    }
-
-    fn disassemble_instruction(&self, offset: usize) -> usize {
-        print!("{:0>4} ", offset);
-        if offset > 0 && self.get_line(offset) == self.get_line(offset - 1) {
-            print!("   | ");
-        } else {
-            print!("{:>4} ", self.get_line(offset))
+        Disassembly {
+            name: name.to_owned(),
+            instructions,
         }
+    }
 
-        let instruction: OpCode = self.code.get(offset).unwrap().try_into().unwrap();
-        match instruction {
-            OpCode::OpReturn => println!("OP_RETURN"),
-            OpCode::OpConstant => {
-                let constant_index = *self.code.get(offset + 1).unwrap() as usize;
-                let constant_value = self.constants.get(constant_index).unwrap();
-                println!("OP_CONSTANT {:>4} {}", constant_index, constant_value)
+    fn disassemble_instruction(&self, offset: usize) -> Instruction {
+        // unverified chunks may hold anything, which is rendered as `<invalid>`
+        let byte = self.code[offset];
+        let op_code = OpCode::try_from(&byte).ok();
+        let operand = match op_code {
+            None => Some(byte as usize),
+            Some(OpCode::OpConstant | OpCode::OpGetGlobal | OpCode::OpCall) => {
+                self.code.get(offset + 1).map(|operand| *operand as usize)
             }
-            OpCode::OpNegate => println!("OP_NEGATE"),
-            OpCode::OpAdd => println!("OP_ADD"),
-            OpCode::OpSubtract => println!("OP_SUBTRACT"),
-            OpCode::OpMultiply => println!("OP_MULTIPLY"),
-            OpCode::OpDivide => println!("OP_DIVIDE"),
-            OpCode::OpTrue => println!("OP_TRUE"),
-            OpCode::OpFalse => println!("OP_FALSE"),
-            OpCode::OpNil => println!("OP_NIL")
+            Some(OpCode::OpConstantLong) => self.code.get(offset + 1..offset + 4).map(read_long_operand),
+            _ => None,
+        };
+        let constant = match op_code {
+            Some(OpCode::OpConstant | OpCode::OpConstantLong) => {
+                operand.and_then(|index| self.constants.get(index).copied())
+            }
+            _ => None,
+        };
+        let name = match op_code {
+            Some(OpCode::OpGetGlobal) => operand.and_then(|index| self.names.get(index).cloned()),
+            _ => None,
+        };
+        let (line, column) = self.get_position(offset).unwrap_or_default();
+        Instruction {
+            offset,
+            line,
+            column,
+            op_code,
+            operand,
+            constant,
+            name,
         }
-        instruction.size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_resolve_line_and_column_of_offset() {
+        let mut chunk = Chunk::new();
+        chunk.write_chunk(OpCode::OpTrue as u8, 1, 1);
+        chunk.write_chunk(OpCode::OpFalse as u8, 1, 6);
+        chunk.write_chunk(OpCode::OpAdd as u8, 1, 6);
+        chunk.write_chunk(OpCode::OpReturn as u8, 2, 3);
+
+        assert_eq!(chunk.get_position(0), Some((1, 1)));
+        assert_eq!(chunk.get_position(1), Some((1, 6)));
+        assert_eq!(chunk.get_position(2), Some((1, 6)));
+        assert_eq!(chunk.get_position(3), Some((2, 3)));
+        assert_eq!(chunk.get_position(4), None);
+        assert_eq!(chunk.lines.len(), 2);
+    }
+
+    #[test]
+    fn should_forget_verification_when_modified() {
+        let mut chunk = Chunk::new();
+        chunk.write_chunk(OpCode::OpNil as u8, 1, 1);
+        chunk.write_chunk(OpCode::OpReturn as u8, 1, 1);
+        assert!(chunk.verify().is_ok());
+        assert!(chunk.is_verified());
+
+        chunk.write_chunk(42, 1, 1);
+        assert!(!chunk.is_verified());
+        assert!(chunk.verify().is_err());
     }
 }
@@ -0,0 +1,292 @@
+use std::mem::size_of;
+
+use crate::{error::BytecodeError, value::Value};
+
+use super::{Chunk, Line};
+
+// Layout, all integers little-endian:
+//
+//   magic       b"FROX"
+//   version     u16
+//   constants   u32 count, then per value a u8 tag and its payload
+//   code        u32 length, then the raw bytes
+//   lines       u32 count, then per run u64 line, u16 length
+//   columns     u32 per byte of code
+//   names       u32 count, then per name u32 length and its UTF-8 bytes
+const MAGIC: &[u8; 4] = b"FROX";
+const FORMAT_VERSION: u16 = 1;
+
+const TAG_NIL: u8 = 0;
+const TAG_FALSE: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_NUMBER: u8 = 3;
+
+impl Chunk {
+    /// Fails if the constant pool holds a native function, those only exist
+    /// within the machine that defined them.
+    pub fn serialize(&self) -> Result<Vec<u8>, BytecodeError> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+
+        write_length(&mut bytes, self.constants.len());
+        for (index, constant) in self.constants.iter().enumerate() {
+            write_value(&mut bytes, *constant).ok_or(BytecodeError::NativeConstant { index })?;
+        }
+
+        write_length(&mut bytes, self.code.len());
+        bytes.extend_from_slice(&self.code);
+
+        write_length(&mut bytes, self.lines.len());
+        for line in &self.lines {
+            bytes.extend_from_slice(&(line.line as u64).to_le_bytes());
+            bytes.extend_from_slice(&line.length.to_le_bytes());
+        }
+        for column in &self.columns {
+            bytes.extend_from_slice(&column.to_le_bytes());
+        }
+
+        write_length(&mut bytes, self.names.len());
+        for name in &self.names {
+            write_length(&mut bytes, name.len());
+            bytes.extend_from_slice(name.as_bytes());
+        }
+        Ok(bytes)
+    }
+
+    /// Reads a chunk written by [`Chunk::serialize`].
+    ///
+    /// Only the container format is checked, the returned chunk is not verified yet.
+    pub fn deserialize(bytes: &[u8]) -> Result<Chunk, BytecodeError> {
+        let mut reader = Reader { bytes, offset: 0 };
+        if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(BytecodeError::InvalidMagic);
+        }
+        let version = reader.u16()?;
+        if version != FORMAT_VERSION {
+            return Err(BytecodeError::UnsupportedVersion {
+                found: version,
+                expected: FORMAT_VERSION,
+            });
+        }
+
+        let mut chunk = Chunk::new();
+        let constant_count = reader.length()?;
+        for _ in 0..constant_count {
+            chunk.constants.push(reader.value()?);
+        }
+
+        let code_length = reader.length()?;
+        chunk.code = reader.take(code_length)?.to_vec();
+
+        let line_count = reader.length()?;
+        for _ in 0..line_count {
+            let line = reader.u64()? as usize;
+            let length = reader.u16()?;
+            chunk.lines.push(Line { line, length });
+        }
+        let covered = chunk.lines.iter().map(|line| line.length as usize).sum();
+        if covered != chunk.code.len() {
+            return Err(BytecodeError::LineTableMismatch {
+                covered,
+                code_length: chunk.code.len(),
+            });
+        }
+        for _ in 0..chunk.code.len() {
+            chunk.columns.push(u32::from_le_bytes(reader.array()?));
+        }
+
+        let name_count = reader.length()?;
+        for _ in 0..name_count {
+            chunk.names.push(reader.name()?);
+        }
+
+        if reader.offset != bytes.len() {
+            return Err(BytecodeError::TrailingData {
+                offset: reader.offset,
+            });
+        }
+        Ok(chunk)
+    }
+}
+
+fn write_length(bytes: &mut Vec<u8>, length: usize) {
+    let length = u32::try_from(length).expect("Chunk sections are limited to u32::MAX entries");
+    bytes.extend_from_slice(&length.to_le_bytes());
+}
+
+fn write_value(bytes: &mut Vec<u8>, value: Value) -> Option<()> {
+    if let Some(number) = value.number() {
+        bytes.push(TAG_NUMBER);
+        bytes.extend_from_slice(&number.to_le_bytes());
+    } else if let Some(boolean) = value.boolean() {
+        bytes.push(if boolean { TAG_TRUE } else { TAG_FALSE });
+    } else if value.is_nil() {
+        bytes.push(TAG_NIL);
+    } else {
+        return None;
+    }
+    Some(())
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], BytecodeError> {
+        let end = self
+            .offset
+            .checked_add(count)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(BytecodeError::Truncated {
+                offset: self.offset,
+            })?;
+        let slice = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], BytecodeError> {
+        Ok(self.take(N)?.try_into().expect("Slice should have the requested length"))
+    }
+
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, BytecodeError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn length(&mut self) -> Result<usize, BytecodeError> {
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+
+    fn name(&mut self) -> Result<String, BytecodeError> {
+        let length = self.length()?;
+        let offset = self.offset;
+        let bytes = self.take(length)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| BytecodeError::InvalidName { offset })
+    }
+
+    fn value(&mut self) -> Result<Value, BytecodeError> {
+        let offset = self.offset;
+        match self.array::<1>()?[0] {
+            TAG_NIL => Ok(Value::Nil),
+            TAG_FALSE => Ok(Value::Boolean(false)),
+            TAG_TRUE => Ok(Value::Boolean(true)),
+            TAG_NUMBER => Ok(Value::Number(f64::from_le_bytes(
+                self.array::<{ size_of::<f64>() }>()?,
+            ))),
+            tag => Err(BytecodeError::UnknownValueTag { offset, tag }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        value::NativeId,
+        virtual_machine::testing::{assert_same_chunk, compile},
+    };
+
+    #[test]
+    fn should_round_trip_chunk() {
+        let chunk = compile("-(1.5 +\n  2) * nil / true - false");
+        let deserialized = Chunk::deserialize(&chunk.serialize().unwrap()).unwrap();
+
+        assert_same_chunk(&deserialized, &chunk, true);
+        assert!(!deserialized.is_verified());
+    }
+
+    #[test]
+    fn should_round_trip_long_constants() {
+        let source = (0..300).map(|i| i.to_string()).collect::<Vec<_>>().join(" + ");
+        let chunk = compile(&source);
+        let deserialized = Chunk::deserialize(&chunk.serialize().unwrap()).unwrap();
+
+        assert_same_chunk(&deserialized, &chunk, true);
+    }
+
+    #[test]
+    fn should_round_trip_names() {
+        let chunk = compile("f(x, g(x))");
+        let deserialized = Chunk::deserialize(&chunk.serialize().unwrap()).unwrap();
+
+        assert_same_chunk(&deserialized, &chunk, true);
+        assert_eq!(deserialized.names, ["f", "x", "g"]);
+    }
+
+    #[test]
+    fn should_reject_foreign_data() {
+        assert_eq!(
+            Chunk::deserialize(b"#!/bin/sh").err(),
+            Some(BytecodeError::InvalidMagic)
+        );
+    }
+
+    #[test]
+    fn should_reject_other_versions() {
+        let mut bytes = compile("1").serialize().unwrap();
+        bytes[4..6].copy_from_slice(&2u16.to_le_bytes());
+        assert_eq!(
+            Chunk::deserialize(&bytes).err(),
+            Some(BytecodeError::UnsupportedVersion {
+                found: 2,
+                expected: FORMAT_VERSION
+            })
+        );
+    }
+
+    #[test]
+    fn should_reject_truncated_input() {
+        let bytes = compile("1 + 2").serialize().unwrap();
+        for length in 6..bytes.len() {
+            assert!(matches!(
+                Chunk::deserialize(&bytes[..length]),
+                Err(BytecodeError::Truncated { .. })
+            ));
+        }
+        // the constant count is cut short right after the version
+        assert_eq!(
+            Chunk::deserialize(&bytes[..8]).err(),
+            Some(BytecodeError::Truncated { offset: 6 })
+        );
+    }
+
+    #[test]
+    fn should_refuse_to_serialize_natives() {
+        let mut chunk = compile("1");
+        chunk.add_constant(Value::Native(NativeId(0)));
+        assert_eq!(chunk.serialize().err(), Some(BytecodeError::NativeConstant { index: 1 }));
+    }
+
+    #[test]
+    fn should_reject_corrupt_input() {
+        let mut bytes = compile("1").serialize().unwrap();
+        // the tag of the first constant follows magic, version and constant count
+        bytes[10] = 42;
+        assert_eq!(
+            Chunk::deserialize(&bytes).err(),
+            Some(BytecodeError::UnknownValueTag { offset: 10, tag: 42 })
+        );
+
+        let mut bytes = compile("x").serialize().unwrap();
+        // the only name is the last section
+        *bytes.last_mut().unwrap() = 0xff;
+        assert!(matches!(
+            Chunk::deserialize(&bytes),
+            Err(BytecodeError::InvalidName { .. })
+        ));
+
+        let mut bytes = compile("1").serialize().unwrap();
+        bytes.push(0);
+        assert!(matches!(
+            Chunk::deserialize(&bytes),
+            Err(BytecodeError::TrailingData { .. })
+        ));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::{
+    ast::{BinaryOperator, Expr, ExprKind, Literal, Position, UnaryOperator},
+    chunk::Chunk,
+    error::CompileError,
+    op_code::OpCode,
+    value::Value,
+};
+use miette::{NamedSource, Result};
+
+const MAX_LONG_OPERAND: usize = (1 << 24) - 1;
+
+/// Lowers an [`Expr`] tree into bytecode.
+///
+/// Instructions are attributed to the position of the last token of the node that produced them.
+pub struct CodeGenerator<'a> {
+    source: &'a str,
+    chunk: &'a mut Chunk,
+}
+
+impl<'a> CodeGenerator<'a> {
+    pub fn new(source: &'a str, chunk: &'a mut Chunk) -> Self {
+        CodeGenerator { source, chunk }
+    }
+
+    pub fn generate(&mut self, expression: &Expr) -> Result<()> {
+        match &expression.kind {
+            ExprKind::Literal(literal) => self.literal(literal, expression),
+            ExprKind::Grouping(inner) => self.generate(inner),
+            ExprKind::Unary { operator, operand } => {
+                self.generate(operand)?;
+                match operator {
+                    UnaryOperator::Negate => self.emit_byte(OpCode::OpNegate as u8, expression.span.end),
+                }
+                Ok(())
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                self.generate(left)?;
+                self.generate(right)?;
+                let op_code = match operator {
+                    BinaryOperator::Add => OpCode::OpAdd,
+                    BinaryOperator::Subtract => OpCode::OpSubtract,
+                    BinaryOperator::Multiply => OpCode::OpMultiply,
+                    BinaryOperator::Divide => OpCode::OpDivide,
+                };
+                self.emit_byte(op_code as u8, expression.span.end);
+                Ok(())
+            }
+            ExprKind::Variable(name) => {
+                let index = self.make_name(name, expression)?;
+                self.emit_bytes(OpCode::OpGetGlobal as u8, index, expression.span.end);
+                Ok(())
+            }
+            ExprKind::Call { callee, arguments } => {
+                self.generate(callee)?;
+                for argument in arguments {
+                    self.generate(argument)?;
+                }
+                let argument_count = u8::try_from(arguments.len()).expect("The parser limits the number of arguments");
+                self.emit_bytes(OpCode::OpCall as u8, argument_count, expression.span.end);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn emit_return(&mut self, position: Position) {
+        self.emit_byte(OpCode::OpReturn as u8, position)
+    }
+
+    fn literal(&mut self, literal: &Literal, expression: &Expr) -> Result<()> {
+        let position = expression.span.end;
+        match literal {
+            Literal::Number(number) => self.emit_constant(Value::Number(*number), expression)?,
+            Literal::Boolean(true) => self.emit_byte(OpCode::OpTrue as u8, position),
+            Literal::Boolean(false) => self.emit_byte(OpCode::OpFalse as u8, position),
+            Literal::Nil => self.emit_byte(OpCode::OpNil as u8, position),
+        }
+        Ok(())
+    }
+
+    fn emit_byte(&mut self, byte: u8, position: Position) {
+        self.chunk.write_chunk(byte, position.line, position.column);
+    }
+
+    fn emit_bytes(&mut self, byte1: u8, byte2: u8, position: Position) {
+        self.emit_byte(byte1, position);
+        self.emit_byte(byte2, position);
+    }
+
+    fn emit_constant(&mut self, value: Value, expression: &Expr) -> Result<()> {
+        let constant_position = self.make_constant(value, expression)?;
+        let position = expression.span.end;
+        match u8::try_from(constant_position) {
+            Ok(short_position) => self.emit_bytes(OpCode::OpConstant as u8, short_position, position),
+            Err(_) => {
+                self.emit_byte(OpCode::OpConstantLong as u8, position);
+                for byte in &constant_position.to_le_bytes()[..3] {
+                    self.emit_byte(*byte, position);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn make_name(&mut self, name: &str, expression: &Expr) -> Result<u8> {
+        u8::try_from(self.chunk.add_name(name)).map_err(|_| {
+            CompileError {
+                msg: "Too many names in one chunk.".to_owned(),
+                src: NamedSource::new("", self.source.to_owned()),
+                span: expression.span.into(),
+            }
+            .into()
+        })
+    }
+
+    fn make_constant(&mut self, value: Value, expression: &Expr) -> Result<usize> {
+        if self.chunk.constants.len() > MAX_LONG_OPERAND {
+            return Err(CompileError {
+                msg: "Too many constants in one chunk.".to_owned(),
+                src: NamedSource::new("", self.source.to_owned()),
+                span: expression.span.into(),
+            }
+            .into());
+        }
+        Ok(self.chunk.add_constant(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn generate(source: &str) -> Chunk {
+        let expression = Parser::new(source).parse().unwrap();
+        let mut chunk = Chunk::new();
+        CodeGenerator::new(source, &mut chunk).generate(&expression).unwrap();
+        chunk
+    }
+
+    #[test]
+    fn should_emit_short_constant() {
+        let chunk = generate("1.5");
+        assert_eq!(chunk.code, vec![OpCode::OpConstant as u8, 0]);
+    }
+
+    #[test]
+    fn should_switch_to_long_constants_after_256_constants() {
+        let source = (0..300).map(|i| i.to_string()).collect::<Vec<_>>().join(" + ");
+        let chunk = generate(&source);
+
+        assert_eq!(chunk.constants.len(), 300);
+        // `0 + 1` followed by 254 times `<constant> +`, then the first long constant
+        let long_constant_offset = 2 + 2 + 1 + 254 * 3;
+        assert_eq!(chunk.code[long_constant_offset - 3], OpCode::OpConstant as u8);
+        assert_eq!(chunk.code[long_constant_offset - 2], 255);
+        assert_eq!(
+            &chunk.code[long_constant_offset..long_constant_offset + 4],
+            &[OpCode::OpConstantLong as u8, 0, 1, 0]
+        );
+    }
+
+    #[test]
+    fn should_emit_call_with_shared_names() {
+        let chunk = generate("f(x, f)");
+
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::OpGetGlobal as u8,
+                0,
+                OpCode::OpGetGlobal as u8,
+                1,
+                OpCode::OpGetGlobal as u8,
+                0,
+                OpCode::OpCall as u8,
+                2,
+            ]
+        );
+        assert_eq!(chunk.names, vec!["f".to_owned(), "x".to_owned()]);
+    }
+}
@@ -1,9 +1,315 @@
-use crate::{op_code::OpCode, InstructionSize};
-
-pub trait ChunkDebug<I>
-where
-    I: InstructionSize + TryInto<OpCode>,
-{
-    fn disassemblee_chunk(&self, name: &str);
-    fn disassemble_instruction(&self, offset: usize) -> usize;
+use std::{
+    fmt::{self, Write},
+    io,
+};
+
+use crate::{op_code::OpCode, value::Value};
+
+pub trait ChunkDebug {
+    fn disassemble(&self, name: &str) -> Disassembly;
+    fn disassemble_instruction(&self, offset: usize) -> Instruction;
+}
+
+/// Output formats of [`Disassembly::render`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The classic `0000    1 OP_CONSTANT    0 1.5` listing, read by the [assembler](crate::assembler).
+    Text,
+    /// The text listing highlighted with ANSI escape codes.
+    Color,
+    /// One JSON object holding the name and the instructions.
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    pub name: String,
+    pub instructions: Vec<Instruction>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    /// `None` if the byte at the offset is no op code.
+    pub op_code: Option<OpCode>,
+    /// The operand, or the byte itself if it is no op code.
+    pub operand: Option<usize>,
+    /// The constant the operand refers to.
+    pub constant: Option<Value>,
+    /// The name the operand refers to.
+    pub name: Option<String>,
+}
+
+const INVALID: &str = "<invalid>";
+
+const RESET: &str = "\x1b[0m";
+const DIMMED: &str = "\x1b[2m";
+const BOLD: &str = "\x1b[1m";
+const YELLOW: &str = "\x1b[33m";
+const GREEN: &str = "\x1b[32m";
+
+impl Disassembly {
+    pub fn render(&self, format: Format, out: &mut impl Write) -> fmt::Result {
+        match format {
+            Format::Text | Format::Color => {
+                writeln!(out, "== {} ==", self.name)?;
+                let mut previous_line = None;
+                for instruction in &self.instructions {
+                    instruction.render(format, previous_line, out)?;
+                    writeln!(out)?;
+                    previous_line = Some(instruction.line);
+                }
+                writeln!(out, "== {} ==", self.name)
+            }
+            Format::Json => {
+                out.write_str("{\"name\":")?;
+                write_json_string(&self.name, out)?;
+                out.write_str(",\"instructions\":[")?;
+                for (index, instruction) in self.instructions.iter().enumerate() {
+                    if index > 0 {
+                        out.write_char(',')?;
+                    }
+                    instruction.render(format, None, out)?;
+                }
+                out.write_str("]}\n")
+            }
+        }
+    }
+
+    pub fn write(&self, format: Format, out: &mut impl io::Write) -> io::Result<()> {
+        let mut rendered = String::new();
+        self.render(format, &mut rendered)
+            .expect("Writing into a String cannot fail");
+        out.write_all(rendered.as_bytes())
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render(Format::Text, f)
+    }
+}
+
+impl Instruction {
+    /// Whether the instruction is an unknown op code, misses operands or refers to
+    /// a missing constant or name, which only happens in unverified chunks.
+    pub fn is_invalid(&self) -> bool {
+        match self.op_code {
+            None => true,
+            Some(OpCode::OpConstant | OpCode::OpConstantLong) => self.constant.is_none(),
+            Some(OpCode::OpGetGlobal) => self.name.is_none(),
+            Some(OpCode::OpCall) => self.operand.is_none(),
+            Some(_) => false,
+        }
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        self.op_code.map_or(INVALID, |op_code| op_code.mnemonic())
+    }
+
+    /// Marks missing operands, constants or names of a known op code.
+    fn has_missing_parts(&self) -> bool {
+        self.op_code.is_some() && self.is_invalid()
+    }
+
+    /// Renders the instruction without a trailing newline. Text listings show
+    /// `|` instead of the line when it matches `previous_line`.
+    pub fn render(&self, format: Format, previous_line: Option<usize>, out: &mut impl Write) -> fmt::Result {
+        let same_line = previous_line == Some(self.line);
+        match format {
+            Format::Text => {
+                write!(out, "{:0>4} ", self.offset)?;
+                if same_line {
+                    out.write_str("   | ")?;
+                } else {
+                    write!(out, "{:>4} ", self.line)?;
+                }
+                out.write_str(self.mnemonic())?;
+                if let Some(operand) = self.operand {
+                    write!(out, " {:>4}", operand)?;
+                }
+                if let Some(constant) = self.constant {
+                    write!(out, " {}", constant)?;
+                }
+                if let Some(name) = &self.name {
+                    write!(out, " {}", name)?;
+                }
+                if self.has_missing_parts() {
+                    write!(out, " {}", INVALID)?;
+                }
+                Ok(())
+            }
+            Format::Color => {
+                write!(out, "{}{:0>4} ", DIMMED, self.offset)?;
+                if same_line {
+                    out.write_str("   | ")?;
+                } else {
+                    write!(out, "{:>4} ", self.line)?;
+                }
+                write!(out, "{}{}{}{}", RESET, BOLD, self.mnemonic(), RESET)?;
+                if let Some(operand) = self.operand {
+                    write!(out, " {}{:>4}{}", YELLOW, operand, RESET)?;
+                }
+                if let Some(constant) = self.constant {
+                    write!(out, " {}{}{}", GREEN, constant, RESET)?;
+                }
+                if let Some(name) = &self.name {
+                    write!(out, " {}{}{}", GREEN, name, RESET)?;
+                }
+                if self.has_missing_parts() {
+                    write!(out, " {}", INVALID)?;
+                }
+                Ok(())
+            }
+            Format::Json => {
+                out.write_char('{')?;
+                self.write_json_fields(out)?;
+                out.write_char('}')
+            }
+        }
+    }
+
+    /// Writes the members of the JSON object, so that others can extend it.
+    pub(crate) fn write_json_fields(&self, out: &mut impl Write) -> fmt::Result {
+        write!(
+            out,
+            "\"offset\":{},\"line\":{},\"column\":{},\"op_code\":\"{}\"",
+            self.offset,
+            self.line,
+            self.column,
+            self.mnemonic()
+        )?;
+        if let Some(operand) = self.operand {
+            write!(out, ",\"operand\":{}", operand)?;
+        }
+        if let Some(constant) = self.constant {
+            out.write_str(",\"constant\":")?;
+            write_json_value(constant, out)?;
+        }
+        if let Some(name) = &self.name {
+            out.write_str(",\"name\":")?;
+            write_json_string(name, out)?;
+        }
+        if self.is_invalid() {
+            out.write_str(",\"invalid\":true")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render(Format::Text, None, f)
+    }
+}
+
+/// Writes numbers and booleans as their JSON counterparts and `nil` as `null`.
+/// Infinities, NaN and functions have no JSON counterpart and are written as strings.
+pub(crate) fn write_json_value(value: Value, out: &mut impl Write) -> fmt::Result {
+    match (value.number(), value.boolean()) {
+        (Some(number), _) if number.is_finite() => write!(out, "{}", number),
+        (None, Some(boolean)) => write!(out, "{}", boolean),
+        _ if value.is_nil() => out.write_str("null"),
+        _ => write_json_string(&value.to_string(), out),
+    }
+}
+
+pub(crate) fn write_json_string(string: &str, out: &mut impl Write) -> fmt::Result {
+    out.write_char('"')?;
+    for c in string.chars() {
+        match c {
+            '"' => out.write_str("\\\"")?,
+            '\\' => out.write_str("\\\\")?,
+            '\n' => out.write_str("\\n")?,
+            c if c.is_control() => write!(out, "\\u{:04x}", c as u32)?,
+            c => out.write_char(c)?,
+        }
+    }
+    out.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, virtual_machine::testing::compile};
+
+    #[test]
+    fn should_render_text_listing() {
+        let disassembly = compile("-1.5 *\n true").disassemble("code");
+
+        assert_eq!(
+            disassembly.to_string(),
+            "== code ==
+0000    1 OP_CONSTANT    0 1.5
+0002    | OP_NEGATE
+0003    2 OP_TRUE
+0004    | OP_MULTIPLY
+0005    | OP_RETURN
+== code ==
+"
+        );
+    }
+
+    #[test]
+    fn should_render_json() {
+        let mut json = String::new();
+        compile("nil + 2")
+            .disassemble("a \"chunk\"")
+            .render(Format::Json, &mut json)
+            .unwrap();
+
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"name":"a \"chunk\"","instructions":["#,
+                r#"{"offset":0,"line":1,"column":1,"op_code":"OP_NIL"},"#,
+                r#"{"offset":1,"line":1,"column":7,"op_code":"OP_CONSTANT","operand":0,"constant":2},"#,
+                r#"{"offset":3,"line":1,"column":7,"op_code":"OP_ADD"},"#,
+                r#"{"offset":4,"line":1,"column":8,"op_code":"OP_RETURN"}]}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[test]
+    fn should_render_colors_around_text() {
+        let disassembly = compile("1").disassemble("code");
+        let mut colored = Vec::new();
+        disassembly.write(Format::Color, &mut colored).unwrap();
+        let colored = String::from_utf8(colored).unwrap();
+
+        assert!(colored.contains(BOLD));
+        let mut stripped = colored;
+        for code in [RESET, DIMMED, BOLD, YELLOW, GREEN] {
+            stripped = stripped.replace(code, "");
+        }
+        assert_eq!(stripped, disassembly.to_string());
+    }
+
+    #[test]
+    fn should_render_invalid_instructions() {
+        let mut chunk = Chunk::new();
+        chunk.write_chunk(42, 1, 1);
+        chunk.write_chunk(OpCode::OpConstant as u8, 1, 1);
+        chunk.write_chunk(3, 1, 1);
+        chunk.write_chunk(OpCode::OpGetGlobal as u8, 2, 1);
+        chunk.write_chunk(0, 2, 1);
+        chunk.write_chunk(OpCode::OpConstantLong as u8, 2, 1);
+        chunk.write_chunk(0, 2, 1);
+        let disassembly = chunk.disassemble("code");
+
+        assert_eq!(
+            disassembly.to_string(),
+            "== code ==
+0000    1 <invalid>   42
+0001    | OP_CONSTANT    3 <invalid>
+0003    2 OP_GET_GLOBAL    0 <invalid>
+0005    | OP_CONSTANT_LONG <invalid>
+== code ==
+"
+        );
+        assert!(disassembly.instructions.iter().all(Instruction::is_invalid));
+    }
 }
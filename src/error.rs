@@ -1,9 +1,13 @@
+use std::{fmt, io};
+
 use miette::{Diagnostic, NamedSource, SourceSpan};
 use thiserror::Error;
 
+use crate::value::Value;
+
 #[derive(Error, Debug, Diagnostic)]
 #[error("{}", msg)]
-pub(crate) struct CompileError {
+pub struct CompileError {
     pub(crate) msg: String,
 
     #[source_code]
@@ -13,26 +17,66 @@ pub(crate) struct CompileError {
     pub(crate) span: SourceSpan,
 }
 
-#[derive(Error, Debug, Diagnostic)]
-#[error("{}", msg)]
-pub(crate) struct RuntimeError {
-    pub(crate) msg: String
+impl CompileError {
+    pub fn message(&self) -> &str {
+        &self.msg
+    }
+
+    /// Byte range of the offending source code.
+    pub fn span(&self) -> SourceSpan {
+        self.span
+    }
 }
 
-impl RuntimeError {
-    pub(crate) fn new(msg: String) -> Self {
-        RuntimeError { msg }
+#[derive(Error, Debug, Diagnostic, Clone)]
+pub struct RuntimeError {
+    pub kind: ExecutionError,
+    /// The failing instruction followed by the calls that executed its code,
+    /// innermost first. Empty if no code ran, like calling a missing global.
+    pub trace: Vec<Frame>,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        for frame in &self.trace {
+            write!(f, "\n{}", frame)?;
+        }
+        Ok(())
     }
 }
 
+/// Position of an instruction taking part in a [`RuntimeError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// Offset of the instruction in its chunk.
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}:{}", self.offset, self.line, self.column)
+    }
+}
+
+/// Failure of [`VirtualMachine::interpret`](crate::virtual_machine::VirtualMachine::interpret)
+/// and its compile and execute steps.
 #[derive(Error, Debug, Diagnostic)]
-#[error("Error occured while interpreting")]
-pub(crate) struct InterpreterError<I>
-where
-    I: Diagnostic,
-{
-    #[related]
-    related: Vec<I>,
+pub enum InterpretError {
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Compile(#[from] CompileError),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Verifier(#[from] VerifierError),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Runtime(#[from] RuntimeError),
+    /// Writing the result or the debug output failed.
+    #[error("Could not write output: {0}")]
+    Output(io::Error),
 }
 
 #[derive(Error, Debug, Diagnostic)]
@@ -41,3 +85,134 @@ pub struct ArgumentError {
     pub msg: String,
 }
 
+/// Failure of an operation on values. Cheap to construct, it is only turned
+/// into a [`RuntimeError`] diagnostic once execution stopped.
+#[derive(Error, Debug, Clone, Copy)]
+pub enum ValueError {
+    #[error("Cannot cast {0} as number")]
+    NotANumber(Value),
+    #[error("Unable to negate {0}, operand must be a number")]
+    NegateOperand(Value),
+    #[error("Unable to {operation} {lhs} and {rhs}, operands must be numbers")]
+    NumberOperands {
+        operation: &'static str,
+        lhs: Value,
+        rhs: Value,
+    },
+}
+
+/// A value that cannot be converted into the Rust type the host asked for.
+#[derive(Error, Debug, Clone, Copy)]
+#[error("expected {expected}, got {found}")]
+pub struct ConversionError {
+    pub expected: &'static str,
+    pub found: Value,
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum ExecutionError {
+    #[error(transparent)]
+    Value(#[from] ValueError),
+    #[error("Undefined variable '{0}'")]
+    UndefinedGlobal(String),
+    #[error("Stack overflow")]
+    StackOverflow,
+    #[error("Execution ran out of fuel")]
+    OutOfFuel,
+    #[error("Execution exceeded its deadline")]
+    DeadlineExceeded,
+    #[error("Execution was interrupted")]
+    Interrupted,
+    #[error("Can only call functions, not {0}")]
+    NotCallable(Value),
+    #[error("{name} expects {expected} arguments but got {found}")]
+    Arity {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    #[error("{name}: {error}")]
+    Native { name: String, error: NativeError },
+}
+
+impl ExecutionError {
+    /// Whether execution hit its fuel, deadline or interrupt limit.
+    pub fn is_limit(&self) -> bool {
+        matches!(
+            self,
+            ExecutionError::OutOfFuel | ExecutionError::DeadlineExceeded | ExecutionError::Interrupted
+        )
+    }
+}
+
+/// Failure reported by a native function. The virtual machine turns it into a
+/// [`RuntimeError`] located at the call.
+#[derive(Error, Debug, Clone)]
+pub enum NativeError {
+    #[error("{0}")]
+    Message(String),
+    /// Code executed by the native failed. Its frames end up in front of the
+    /// call, limits it hit stop the caller as well.
+    #[error("{}", .0.kind)]
+    Runtime(Box<RuntimeError>),
+}
+
+impl NativeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        NativeError::Message(message.into())
+    }
+}
+
+/// Lets natives pass on failures of code they execute, keeping its location.
+impl From<InterpretError> for NativeError {
+    fn from(error: InterpretError) -> Self {
+        match error {
+            InterpretError::Runtime(error) => error.into(),
+            error => NativeError::new(error.to_string()),
+        }
+    }
+}
+
+impl From<RuntimeError> for NativeError {
+    fn from(error: RuntimeError) -> Self {
+        NativeError::Runtime(Box::new(error))
+    }
+}
+
+#[derive(Error, Debug, Diagnostic, Clone, Copy, PartialEq, Eq)]
+pub enum BytecodeError {
+    #[error("Not a fast-frox bytecode file")]
+    InvalidMagic,
+    #[error("Unsupported bytecode format version {found}, expected {expected}")]
+    UnsupportedVersion { found: u16, expected: u16 },
+    #[error("Bytecode is truncated at offset {offset}")]
+    Truncated { offset: usize },
+    #[error("Unknown value tag {tag} at offset {offset}")]
+    UnknownValueTag { offset: usize, tag: u8 },
+    #[error("Name at offset {offset} is not valid UTF-8")]
+    InvalidName { offset: usize },
+    #[error("Line table covers {covered} bytes but the code has {code_length}")]
+    LineTableMismatch { covered: usize, code_length: usize },
+    #[error("Unexpected trailing data at offset {offset}")]
+    TrailingData { offset: usize },
+    #[error("Constant {index} is a native function and cannot be serialized")]
+    NativeConstant { index: usize },
+}
+
+#[derive(Error, Debug, Diagnostic, Clone, Copy, PartialEq, Eq)]
+pub enum VerifierError {
+    #[error("Unknown op code {byte} at offset {offset}")]
+    UnknownOpCode { offset: usize, byte: u8 },
+    #[error("Instruction at offset {offset} is missing operands")]
+    TruncatedInstruction { offset: usize },
+    #[error("Instruction at offset {offset} refers to missing constant {index}")]
+    ConstantOutOfBounds { offset: usize, index: usize },
+    #[error("Instruction at offset {offset} refers to missing name {index}")]
+    NameOutOfBounds { offset: usize, index: usize },
+    #[error("Instruction at offset {offset} pops from an empty stack")]
+    StackUnderflow { offset: usize },
+    #[error("Instruction at offset {offset} exceeds the maximum stack size")]
+    StackOverflow { offset: usize },
+    #[error("Chunk does not end with a return instruction")]
+    MissingReturn,
+}
@@ -1,10 +1,16 @@
+pub mod assembler;
+pub mod ast;
 pub mod chunk;
+pub mod code_generator;
 pub(crate) mod compiler;
 pub mod debug;
+pub mod native;
 pub mod op_code;
-pub(crate) mod peek_peek_iterator;
-pub(crate) mod scanner;
+pub mod parser;
+pub mod scanner;
+pub mod tracer;
 pub mod value;
+pub mod verifier;
 pub mod virtual_machine;
 pub mod error;
 
@@ -1,45 +1,215 @@
-use std::{
-    env, fs,
-    io::{self, BufRead, Write},
-};
-use fast_frox::error::ArgumentError;
+mod repl;
+
+use std::{env, fs, io, process::ExitCode};
+use fast_frox::debug::ChunkDebug;
+use fast_frox::error::{ArgumentError, InterpretError};
+use fast_frox::tracer::{TextTracer, Tracer};
 use fast_frox::virtual_machine::VirtualMachine;
-use miette::Result;
+use miette::Report;
+
+const USAGE: &str = "Usage: fast-frox [options] [command]
+
+Commands:
+  run <file> [args...]  Run a script, the default for a bare <file>
+  repl                  Start an interactive session, the default without command
+  dis <file>            Print the bytecode of a script
+  -e <expr>             Evaluate an expression
+
+Options:
+  --trace               Print the stack and every instruction while executing
+  --disassemble         Print the bytecode before executing
+  -h, --help            Print this help";
+
+// Exit codes following sysexits.h
+const EX_USAGE: u8 = 64;
+const EX_DATAERR: u8 = 65;
+const EX_SOFTWARE: u8 = 70;
+const EX_IOERR: u8 = 74;
 
-pub(crate) static DEBUG: bool = true;
+#[derive(Debug, PartialEq)]
+enum Command {
+    Run(String, Vec<String>),
+    Repl,
+    Eval(String),
+    Disassemble(String),
+    Help,
+}
 
-fn main() -> Result<()> {
-    let args = env::args().collect::<Vec<_>>();
+#[derive(Debug, PartialEq)]
+struct Cli {
+    command: Command,
+    trace: bool,
+    disassemble: bool,
+}
 
-    let mut vm = VirtualMachine::new(DEBUG);
-    vm.init();
+enum Failure {
+    Usage(ArgumentError),
+    Io(String, io::Error),
+    Trace(io::Error),
+    Interpret(InterpretError),
+}
 
-    let result = match args.as_slice() {
-        [_] => repl(&mut vm),
-        [_, path] => run_file(path, &mut vm),
-        _ => Err(ArgumentError { msg: "Usage: fast-frox [path]".to_owned() }.into() ),
-    };
+fn main() -> ExitCode {
+    let result = parse_args(env::args().skip(1)).map_err(Failure::Usage).and_then(|cli| {
+        // `dis` prints the listing itself
+        let debug = cli.disassemble && !matches!(cli.command, Command::Disassemble(_));
+        if cli.command == Command::Repl {
+            let mut vm = VirtualMachine::with_tracer(debug, cli.trace.then(TextTracer::stdout));
+            repl::run(&mut vm).map_err(|error| Failure::Io("the terminal".to_owned(), io::Error::other(error)))
+        } else if cli.trace {
+            let mut vm = VirtualMachine::with_tracer(debug, TextTracer::stdout());
+            let result = execute(cli.command, &mut vm);
+            match vm.tracer_mut().take_error() {
+                Some(error) => result.and(Err(Failure::Trace(error))),
+                None => result,
+            }
+        } else {
+            execute(cli.command, &mut VirtualMachine::new(debug))
+        }
+    });
 
-    drop(vm);
-    result
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Usage(error)) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            ExitCode::from(EX_USAGE)
+        }
+        Err(Failure::Io(path, error)) => {
+            eprintln!("Could not read {}: {}", path, error);
+            ExitCode::from(EX_IOERR)
+        }
+        Err(Failure::Trace(error)) => {
+            eprintln!("Could not write the trace: {}", error);
+            ExitCode::from(EX_IOERR)
+        }
+        Err(Failure::Interpret(error)) => {
+            let code = match error {
+                InterpretError::Compile(_) | InterpretError::Verifier(_) => EX_DATAERR,
+                InterpretError::Runtime(_) => EX_SOFTWARE,
+                InterpretError::Output(_) => EX_IOERR,
+            };
+            eprintln!("{:?}", Report::new(error));
+            ExitCode::from(code)
+        }
+    }
 }
 
-fn repl(vm: &mut VirtualMachine) -> Result<()> {
-    let stdin = io::stdin();
-    loop {
-        print!("> ");
-        io::stdout().flush().unwrap();
-        let mut buffer = String::new();
-        stdin.lock().read_line(&mut buffer).unwrap();
-        buffer = buffer.trim().to_string();
-        if buffer.is_empty() {
-            return Ok(());
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Cli, ArgumentError> {
+    let usage_error = |msg: String| ArgumentError { msg };
+    let mut args = args.into_iter();
+    let mut command = None;
+    let mut trace = false;
+    let mut disassemble = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--trace" => trace = true,
+            "--disassemble" => disassemble = true,
+            "-h" | "--help" => command = Some(Command::Help),
+            _ if command.is_some() => return Err(usage_error(format!("Unexpected argument `{}`", arg))),
+            "-e" => {
+                let expression = args.next().ok_or_else(|| usage_error("Missing expression after -e".to_owned()))?;
+                command = Some(Command::Eval(expression));
+            }
+            "repl" => command = Some(Command::Repl),
+            "dis" => {
+                let path = args.next().ok_or_else(|| usage_error("Missing file to disassemble".to_owned()))?;
+                command = Some(Command::Disassemble(path));
+            }
+            "run" => {
+                let path = args.next().ok_or_else(|| usage_error("Missing file to run".to_owned()))?;
+                command = Some(Command::Run(path, args.by_ref().collect()));
+            }
+            _ if arg.starts_with('-') => return Err(usage_error(format!("Unknown option `{}`", arg))),
+            _ => command = Some(Command::Run(arg, args.by_ref().collect())),
         }
-        vm.interpret(buffer.as_str())?;
     }
+
+    Ok(Cli {
+        command: command.unwrap_or(Command::Repl),
+        trace,
+        disassemble,
+    })
 }
 
-fn run_file(path: &str, vm: &mut VirtualMachine) -> Result<()> {
-    let source = fs::read_to_string(path).expect("Should have been able to read the file");
-    vm.interpret(source.as_str())
+fn execute<T: Tracer>(command: Command, vm: &mut VirtualMachine<T>) -> Result<(), Failure> {
+    match command {
+        // Scripts cannot read their arguments until the language has strings
+        Command::Run(path, _arguments) => interpret(&read_file(&path)?, vm),
+        Command::Repl => unreachable!("The REPL brings its own virtual machine"),
+        Command::Eval(expression) => interpret(&expression, vm),
+        Command::Disassemble(path) => {
+            let chunk = vm.compile(&read_file(&path)?).map_err(Failure::Interpret)?;
+            print!("{}", chunk.disassemble(&path));
+            Ok(())
+        }
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    }
+}
+
+fn interpret<T: Tracer>(source: &str, vm: &mut VirtualMachine<T>) -> Result<(), Failure> {
+    vm.interpret(source).map(drop).map_err(Failure::Interpret)
+}
+
+fn read_file(path: &str) -> Result<String, Failure> {
+    fs::read_to_string(path).map_err(|error| Failure::Io(path.to_owned(), error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Cli, String> {
+        parse_args(args.iter().map(|arg| arg.to_string())).map_err(|error| error.msg)
+    }
+
+    fn command(command: Command) -> Result<Cli, String> {
+        Ok(Cli {
+            command,
+            trace: false,
+            disassemble: false,
+        })
+    }
+
+    #[test]
+    fn should_parse_commands() {
+        assert_eq!(parse(&[]), command(Command::Repl));
+        assert_eq!(parse(&["repl"]), command(Command::Repl));
+        assert_eq!(parse(&["-e", "1 + 2"]), command(Command::Eval("1 + 2".to_owned())));
+        assert_eq!(parse(&["dis", "a.frox"]), command(Command::Disassemble("a.frox".to_owned())));
+        assert_eq!(parse(&["--help"]), command(Command::Help));
+    }
+
+    #[test]
+    fn should_run_a_bare_file() {
+        let run = |path: &str, arguments: &[&str]| {
+            command(Command::Run(path.to_owned(), arguments.iter().map(|arg| arg.to_string()).collect()))
+        };
+
+        assert_eq!(parse(&["run", "a.frox"]), run("a.frox", &[]));
+        assert_eq!(parse(&["a.frox"]), run("a.frox", &[]));
+        // everything after the script belongs to it, options included
+        assert_eq!(parse(&["a.frox", "x", "--trace"]), run("a.frox", &["x", "--trace"]));
+        assert_eq!(parse(&["run", "a.frox", "-e", "1"]), run("a.frox", &["-e", "1"]));
+    }
+
+    #[test]
+    fn should_parse_toggles_around_command() {
+        let cli = parse(&["--trace", "dis", "a.frox", "--disassemble"]).unwrap();
+
+        assert_eq!(cli.command, Command::Disassemble("a.frox".to_owned()));
+        assert!(cli.trace);
+        assert!(cli.disassemble);
+    }
+
+    #[test]
+    fn should_reject_invalid_usage() {
+        assert_eq!(parse(&["-e"]), Err("Missing expression after -e".to_owned()));
+        assert_eq!(parse(&["run"]), Err("Missing file to run".to_owned()));
+        assert_eq!(parse(&["--verbose"]), Err("Unknown option `--verbose`".to_owned()));
+        assert_eq!(parse(&["repl", "dis"]), Err("Unexpected argument `dis`".to_owned()));
+    }
 }
@@ -0,0 +1,75 @@
+use std::rc::Rc;
+
+use crate::{
+    error::NativeError,
+    tracer::Tracer,
+    value::{FromValue, IntoValue, Value},
+    virtual_machine::VirtualMachine,
+};
+
+/// Host function callable from scripts. It receives the calling machine and
+/// exactly as many arguments as it declared.
+pub type NativeFn<T> = dyn Fn(&mut VirtualMachine<T>, &[Value]) -> Result<Value, NativeError>;
+
+pub(crate) struct Native<T: Tracer> {
+    pub(crate) name: String,
+    pub(crate) arity: usize,
+    pub(crate) function: Rc<NativeFn<T>>,
+}
+
+/// Rust functions taking up to six [`FromValue`] parameters, registered with
+/// [`VirtualMachine::define_function`]. `Args` is the tuple of parameter types.
+pub trait TypedNative<Args> {
+    const ARITY: usize;
+
+    /// Converts exactly [`Self::ARITY`] arguments and calls the function.
+    fn call(&self, arguments: &[Value]) -> Result<Value, NativeError>;
+}
+
+/// Results of typed natives, either a value or a value and a possible failure.
+pub trait IntoNativeResult {
+    fn into_native_result(self) -> Result<Value, NativeError>;
+}
+
+impl<R: IntoValue> IntoNativeResult for R {
+    fn into_native_result(self) -> Result<Value, NativeError> {
+        Ok(self.into_value())
+    }
+}
+
+impl<R: IntoValue> IntoNativeResult for Result<R, NativeError> {
+    fn into_native_result(self) -> Result<Value, NativeError> {
+        self.map(IntoValue::into_value)
+    }
+}
+
+/// Converts the argument at the 1-based `position`.
+fn argument<A: FromValue>(position: usize, value: Value) -> Result<A, NativeError> {
+    A::from_value(value).map_err(|error| NativeError::new(format!("argument {} {}", position, error)))
+}
+
+macro_rules! impl_typed_native {
+    ($arity:literal; $($argument:ident $position:literal),*) => {
+        impl<F, R, $($argument),*> TypedNative<($($argument,)*)> for F
+        where
+            F: Fn($($argument),*) -> R,
+            R: IntoNativeResult,
+            $($argument: FromValue,)*
+        {
+            const ARITY: usize = $arity;
+
+            #[allow(unused_variables)]
+            fn call(&self, arguments: &[Value]) -> Result<Value, NativeError> {
+                self($(argument::<$argument>($position, arguments[$position - 1])?),*).into_native_result()
+            }
+        }
+    };
+}
+
+impl_typed_native!(0;);
+impl_typed_native!(1; A 1);
+impl_typed_native!(2; A 1, B 2);
+impl_typed_native!(3; A 1, B 2, C 3);
+impl_typed_native!(4; A 1, B 2, C 3, D 4);
+impl_typed_native!(5; A 1, B 2, C 3, D 4, E 5);
+impl_typed_native!(6; A 1, B 2, C 3, D 4, E 5, G 6);
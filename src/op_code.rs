@@ -1,7 +1,7 @@
 use crate::InstructionSize;
 
 #[repr(u8)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OpCode {
     OpReturn = 0,
     OpConstant = 1,
@@ -12,7 +12,10 @@ pub enum OpCode {
     OpDivide = 6,
     OpNil = 7,
     OpTrue = 8,
-    OpFalse = 9
+    OpFalse = 9,
+    OpConstantLong = 10,
+    OpGetGlobal = 11,
+    OpCall = 12,
 }
 
 impl InstructionSize for OpCode {
@@ -27,27 +30,95 @@ impl InstructionSize for OpCode {
             | Self::OpNil
             | Self::OpTrue
             | Self::OpFalse => 1,
-            Self::OpConstant => 2,
+            Self::OpConstant | Self::OpGetGlobal | Self::OpCall => 2,
+            Self::OpConstantLong => 4,
         }
     }
 }
 
+impl OpCode {
+    /// All op codes, indexed by their byte value.
+    pub const ALL: [OpCode; 13] = [
+        OpCode::OpReturn,
+        OpCode::OpConstant,
+        OpCode::OpNegate,
+        OpCode::OpAdd,
+        OpCode::OpSubtract,
+        OpCode::OpMultiply,
+        OpCode::OpDivide,
+        OpCode::OpNil,
+        OpCode::OpTrue,
+        OpCode::OpFalse,
+        OpCode::OpConstantLong,
+        OpCode::OpGetGlobal,
+        OpCode::OpCall,
+    ];
+
+    /// Name of the op code in disassembly listings.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            OpCode::OpReturn => "OP_RETURN",
+            OpCode::OpConstant => "OP_CONSTANT",
+            OpCode::OpNegate => "OP_NEGATE",
+            OpCode::OpAdd => "OP_ADD",
+            OpCode::OpSubtract => "OP_SUBTRACT",
+            OpCode::OpMultiply => "OP_MULTIPLY",
+            OpCode::OpDivide => "OP_DIVIDE",
+            OpCode::OpNil => "OP_NIL",
+            OpCode::OpTrue => "OP_TRUE",
+            OpCode::OpFalse => "OP_FALSE",
+            OpCode::OpConstantLong => "OP_CONSTANT_LONG",
+            OpCode::OpGetGlobal => "OP_GET_GLOBAL",
+            OpCode::OpCall => "OP_CALL",
+        }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str) -> Option<OpCode> {
+        OpCode::ALL
+            .iter()
+            .copied()
+            .find(|op_code| op_code.mnemonic() == mnemonic)
+    }
+}
+
+impl OpCode {
+    /// # Safety
+    ///
+    /// `byte` must be the value of one of the op codes.
+    #[inline(always)]
+    pub(crate) unsafe fn from_byte_unchecked(byte: u8) -> OpCode {
+        debug_assert!((byte as usize) < OpCode::ALL.len());
+        std::mem::transmute::<u8, OpCode>(byte)
+    }
+}
+
 impl TryFrom<&u8> for OpCode {
     type Error = String;
 
     fn try_from(value: &u8) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(OpCode::OpReturn),
-            1 => Ok(OpCode::OpConstant),
-            2 => Ok(OpCode::OpNegate),
-            3 => Ok(OpCode::OpAdd),
-            4 => Ok(OpCode::OpSubtract),
-            5 => Ok(OpCode::OpMultiply),
-            6 => Ok(OpCode::OpDivide),
-            7 => Ok(OpCode::OpNil),
-            8 => Ok(OpCode::OpTrue),
-            9 => Ok(OpCode::OpFalse),
-            _ => Err("unknown value".to_string()),
+        OpCode::ALL
+            .get(*value as usize)
+            .copied()
+            .ok_or_else(|| "unknown value".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_list_op_codes_by_byte_value() {
+        for (byte, op_code) in OpCode::ALL.iter().enumerate() {
+            assert_eq!(*op_code as usize, byte);
+        }
+    }
+
+    #[test]
+    fn should_resolve_op_codes_by_mnemonic() {
+        for op_code in OpCode::ALL {
+            assert_eq!(OpCode::from_mnemonic(op_code.mnemonic()), Some(op_code));
         }
+        assert_eq!(OpCode::from_mnemonic("OP_JUMP"), None);
     }
 }
@@ -0,0 +1,385 @@
+use crate::{
+    ast::{BinaryOperator, Expr, ExprKind, Literal, Span, UnaryOperator},
+    error::CompileError,
+    scanner::{Scanner, Token, TokenType},
+};
+use miette::{NamedSource, Result};
+
+/// Arguments of a call are counted by a single byte operand.
+const MAX_ARGUMENTS: usize = u8::MAX as usize;
+
+/// Pratt parser producing an [`Expr`] tree from source code.
+pub struct Parser<'a> {
+    source: &'a str,
+    scanner: Scanner<'a>,
+    previous: Option<Token>,
+    current: Option<Token>,
+}
+
+#[repr(u8)]
+#[derive(Clone, PartialEq, PartialOrd)]
+enum Precedence {
+    None,
+    Assignment,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+struct ParseRule<'a> {
+    prefix_fn: Option<fn(&mut Parser<'a>) -> Result<Expr>>,
+    infix_fn: Option<fn(&mut Parser<'a>, Expr) -> Result<Expr>>,
+    precedence: Precedence,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Parser {
+            source,
+            scanner: Scanner::new(source),
+            previous: None,
+            current: None,
+        }
+    }
+
+    /// Parses the whole source as a single expression.
+    pub fn parse(&mut self) -> Result<Expr> {
+        self.advance()?;
+        let expression = self.expression()?;
+        self.consume(TokenType::Eof)?;
+        Ok(expression)
+    }
+
+    /// The `Eof` token, available once [`Parser::parse`] succeeded.
+    pub fn eof(&self) -> Option<Token> {
+        self.previous.filter(|token| token.tpe == TokenType::Eof)
+    }
+
+    fn advance(&mut self) -> Result<()> {
+        self.previous = self.current.take();
+        self.current = Some(self.scanner.scan_token()?);
+        Ok(())
+    }
+
+    fn consume(&mut self, expected_type: TokenType) -> Result<()> {
+        if self.current.as_ref().map(|token| &token.tpe) == Some(&expected_type) {
+            self.advance()?;
+            Ok(())
+        } else {
+            let current_token = self.current.take().unwrap();
+            // a label past the last character is not rendered, so point at the last token instead
+            let span = match (current_token.tpe, self.previous) {
+                (TokenType::Eof, Some(previous)) => previous.into(),
+                _ => current_token.into(),
+            };
+            Err(CompileError {
+                msg: format!(
+                    "Expected token of type {:?} at {}:{}",
+                    expected_type, current_token.line, current_token.column
+                ),
+                src: NamedSource::new("", self.source.to_owned()),
+                span,
+            }
+            .into())
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr> {
+        self.parse_precedence(Precedence::Assignment)
+    }
+
+    fn number(&mut self) -> Result<Expr> {
+        let previous = *self.previous();
+        let value = previous.lexeme(self.source).parse::<f64>().unwrap();
+
+        Ok(Expr::new(
+            ExprKind::Literal(Literal::Number(value)),
+            previous.into(),
+        ))
+    }
+
+    fn grouping(&mut self) -> Result<Expr> {
+        let start: Span = (*self.previous()).into();
+        let expression = self.expression()?;
+        self.consume(TokenType::RightParen)?;
+
+        let span = start.to((*self.previous()).into());
+        Ok(Expr::new(ExprKind::Grouping(Box::new(expression)), span))
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        let operator_token = *self.previous();
+        let operand = self.parse_precedence(Precedence::Unary)?;
+
+        let operator = match operator_token.tpe {
+            TokenType::Minus => UnaryOperator::Negate,
+            _ => return Err(self.error(operator_token, "Not a unary operator")),
+        };
+        let span = Span::from(operator_token).to(operand.span);
+        Ok(Expr::new(
+            ExprKind::Unary {
+                operator,
+                operand: Box::new(operand),
+            },
+            span,
+        ))
+    }
+
+    fn binary(&mut self, left: Expr) -> Result<Expr> {
+        let operator_token = *self.previous();
+        let rule = Parser::get_rule(&operator_token.tpe);
+        let right = self.parse_precedence(rule.precedence.next())?;
+
+        let operator = match operator_token.tpe {
+            TokenType::Plus => BinaryOperator::Add,
+            TokenType::Minus => BinaryOperator::Subtract,
+            TokenType::Star => BinaryOperator::Multiply,
+            TokenType::Slash => BinaryOperator::Divide,
+            _ => return Err(self.error(operator_token, "Not a binary operator")),
+        };
+        let span = left.span.to(right.span);
+        Ok(Expr::new(
+            ExprKind::Binary {
+                operator,
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+            span,
+        ))
+    }
+
+    fn variable(&mut self) -> Result<Expr> {
+        let token = *self.previous();
+        let name = token.lexeme(self.source).to_owned();
+        Ok(Expr::new(ExprKind::Variable(name), token.into()))
+    }
+
+    fn call(&mut self, callee: Expr) -> Result<Expr> {
+        let mut arguments = Vec::new();
+        if self.current().tpe != TokenType::RightParen {
+            loop {
+                let argument = self.expression()?;
+                if arguments.len() == MAX_ARGUMENTS {
+                    return Err(CompileError {
+                        msg: format!("Can't have more than {} arguments.", MAX_ARGUMENTS),
+                        src: NamedSource::new("", self.source.to_owned()),
+                        span: argument.span.into(),
+                    }
+                    .into());
+                }
+                arguments.push(argument);
+                if self.current().tpe != TokenType::Comma {
+                    break;
+                }
+                self.advance()?;
+            }
+        }
+        self.consume(TokenType::RightParen)?;
+
+        let span = callee.span.to((*self.previous()).into());
+        Ok(Expr::new(
+            ExprKind::Call {
+                callee: Box::new(callee),
+                arguments,
+            },
+            span,
+        ))
+    }
+
+    fn literal(&mut self) -> Result<Expr> {
+        let token = *self.previous();
+        let literal = match token.tpe {
+            TokenType::False => Literal::Boolean(false),
+            TokenType::True => Literal::Boolean(true),
+            TokenType::Nil => Literal::Nil,
+            _ => return Err(self.error(token, "Not a literal")),
+        };
+        Ok(Expr::new(ExprKind::Literal(literal), token.into()))
+    }
+
+    fn parse_precedence(&mut self, precedence: Precedence) -> Result<Expr> {
+        self.advance()?;
+        let previous = *self.previous();
+        let prefix_rule = match Parser::get_rule(&previous.tpe).prefix_fn {
+            Some(prefix_rule) => prefix_rule,
+            None => return Err(self.error(previous, "Expected expression")),
+        };
+        let mut expression = prefix_rule(self)?;
+
+        while precedence <= Parser::get_rule(&self.current().tpe).precedence {
+            self.advance()?;
+            let infix_rule = Parser::get_rule(&self.previous().tpe)
+                .infix_fn
+                .expect("No infix function found");
+            expression = infix_rule(self, expression)?;
+        }
+        Ok(expression)
+    }
+
+    fn get_rule(operator_type: &TokenType) -> ParseRule<'a> {
+        match operator_type {
+            TokenType::LeftParen => ParseRule {
+                prefix_fn: Some(Parser::grouping),
+                infix_fn: Some(Parser::call),
+                precedence: Precedence::Call,
+            },
+            TokenType::Minus => ParseRule {
+                prefix_fn: Some(Parser::unary),
+                infix_fn: Some(Parser::binary),
+                precedence: Precedence::Term,
+            },
+            TokenType::Plus => ParseRule {
+                prefix_fn: None,
+                infix_fn: Some(Parser::binary),
+                precedence: Precedence::Term,
+            },
+            TokenType::Slash => ParseRule {
+                prefix_fn: None,
+                infix_fn: Some(Parser::binary),
+                precedence: Precedence::Factor,
+            },
+            TokenType::Star => ParseRule {
+                prefix_fn: None,
+                infix_fn: Some(Parser::binary),
+                precedence: Precedence::Factor,
+            },
+            TokenType::Number => ParseRule {
+                prefix_fn: Some(Parser::number),
+                infix_fn: None,
+                precedence: Precedence::None,
+            },
+            TokenType::Identifier => ParseRule {
+                prefix_fn: Some(Parser::variable),
+                infix_fn: None,
+                precedence: Precedence::None,
+            },
+            TokenType::True | TokenType::False | TokenType::Nil => ParseRule {
+                prefix_fn: Some(Parser::literal),
+                infix_fn: None,
+                precedence: Precedence::None,
+            },
+            _ => ParseRule {
+                prefix_fn: None,
+                infix_fn: None,
+                precedence: Precedence::None,
+            },
+        }
+    }
+
+    fn error(&self, token: Token, msg: &str) -> miette::Report {
+        CompileError {
+            msg: msg.to_owned(),
+            src: NamedSource::new("", self.source.to_owned()),
+            span: token.into(),
+        }
+        .into()
+    }
+
+    fn current(&self) -> &Token {
+        self.current.as_ref().expect("No value present")
+    }
+
+    fn previous(&self) -> &Token {
+        self.previous.as_ref().expect("No value present")
+    }
+}
+
+impl Precedence {
+    fn next(&self) -> Precedence {
+        let enum_value: u8 = self.clone() as u8;
+        (enum_value + 1).try_into().unwrap()
+    }
+}
+
+impl TryFrom<u8> for Precedence {
+    type Error = String;
+
+    fn try_from(value: u8) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Precedence::None),
+            1 => Ok(Precedence::Assignment),
+            2 => Ok(Precedence::Or),
+            3 => Ok(Precedence::And),
+            4 => Ok(Precedence::Equality),
+            5 => Ok(Precedence::Comparison),
+            6 => Ok(Precedence::Term),
+            7 => Ok(Precedence::Factor),
+            8 => Ok(Precedence::Unary),
+            9 => Ok(Precedence::Call),
+            10 => Ok(Precedence::Primary),
+            _ => Err("unknown value".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::Position;
+
+    #[test]
+    fn should_respect_operator_precedence() {
+        let expression = Parser::new("1 + 2 * 3").parse().unwrap();
+        let ExprKind::Binary { operator, right, .. } = expression.kind else {
+            panic!("Expected binary expression");
+        };
+        assert_eq!(operator, BinaryOperator::Add);
+        assert!(matches!(
+            right.kind,
+            ExprKind::Binary {
+                operator: BinaryOperator::Multiply,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn should_span_whole_expression() {
+        let expression = Parser::new("-(1 +\n  2)").parse().unwrap();
+        assert_eq!(
+            expression.span,
+            Span {
+                start: 0,
+                length: 10,
+                begin: Position { line: 1, column: 1 },
+                end: Position { line: 2, column: 4 },
+            }
+        );
+    }
+
+    #[test]
+    fn should_parse_calls() {
+        let expression = Parser::new("f(1, g())(x)").parse().unwrap();
+        let ExprKind::Call { callee, arguments } = expression.kind else {
+            panic!("Expected call");
+        };
+        assert_eq!(arguments.len(), 1);
+        assert_eq!(arguments[0].kind, ExprKind::Variable("x".to_owned()));
+
+        let ExprKind::Call { callee, arguments } = callee.kind else {
+            panic!("Expected inner call");
+        };
+        assert_eq!(callee.kind, ExprKind::Variable("f".to_owned()));
+        assert_eq!(arguments.len(), 2);
+        assert!(matches!(&arguments[1].kind, ExprKind::Call { arguments, .. } if arguments.is_empty()));
+    }
+
+    #[test]
+    fn should_limit_arguments() {
+        let source = format!("f({})", vec!["1"; 256].join(", "));
+        let error = Parser::new(&source).parse().unwrap_err();
+        assert_eq!(error.to_string(), "Can't have more than 255 arguments.");
+    }
+
+    #[test]
+    fn should_fail_on_missing_expression() {
+        let error = Parser::new("1 + )").parse().unwrap_err();
+        assert_eq!(error.to_string(), "Expected expression");
+    }
+}
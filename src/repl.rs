@@ -0,0 +1,268 @@
+mod helper;
+
+use std::{
+    env, fs,
+    io::Stdout,
+    path::PathBuf,
+    time::Instant,
+};
+
+use fast_frox::{
+    debug::ChunkDebug,
+    error::CompileError,
+    scanner::{Scanner, TokenType},
+    tracer::TextTracer,
+    virtual_machine::VirtualMachine,
+};
+use miette::Report;
+use rustyline::{error::ReadlineError, history::FileHistory, Editor};
+
+use self::helper::ReplHelper;
+
+const PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = ". ";
+const HISTORY_FILE: &str = ".fast_frox_history";
+
+const HELP: &str = "Commands:
+  :dis <expr>       Print the bytecode of an expression
+  :globals          Print the defined globals
+  :load <file>      Run a script
+  :reset            Start over with a fresh machine
+  :time <expr>      Evaluate an expression and print how long it took
+  :trace on|off     Print every executed instruction
+  :help             Print this help";
+
+const META_COMMANDS: [&str; 7] = ["dis", "globals", "load", "reset", "time", "trace", "help"];
+
+/// The REPL traces while the tracer is `Some`.
+pub(crate) type ReplVirtualMachine = VirtualMachine<Option<TextTracer<Stdout>>>;
+
+#[derive(Debug, PartialEq)]
+enum MetaCommand<'a> {
+    Disassemble(&'a str),
+    Globals,
+    Load(&'a str),
+    Reset,
+    Time(&'a str),
+    Trace(bool),
+    Help,
+}
+
+/// Reads and interprets input until Ctrl-D. Ctrl-C discards the current input.
+pub(crate) fn run(vm: &mut ReplVirtualMachine) -> Result<(), ReadlineError> {
+    let mut editor = Editor::new()?;
+    editor.set_helper(Some(ReplHelper::default()));
+    let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+    if let Some(history) = &history {
+        // There is no history before the first session
+        let _ = editor.load_history(history);
+    }
+
+    loop {
+        if let Some(helper) = editor.helper_mut() {
+            helper.globals = vm.globals().map(|(name, _)| name.to_owned()).collect();
+        }
+        let input = match read_input(&mut editor)? {
+            Some(input) => input,
+            None => break,
+        };
+        if input.trim().is_empty() {
+            continue;
+        }
+        editor.add_history_entry(input.as_str())?;
+        match input.trim_start().strip_prefix(':') {
+            Some(command) => match MetaCommand::parse(command) {
+                Ok(command) => command.execute(vm),
+                Err(error) => eprintln!("{}", error),
+            },
+            None => interpret(&input, vm),
+        }
+    }
+
+    if let Some(history) = &history {
+        editor.save_history(history)?;
+    }
+    Ok(())
+}
+
+fn interpret(source: &str, vm: &mut ReplVirtualMachine) {
+    if let Err(error) = vm.interpret(source) {
+        eprintln!("{:?}", Report::new(error));
+    }
+    if let Some(error) = vm.tracer_mut().as_mut().and_then(TextTracer::take_error) {
+        eprintln!("Could not write the trace: {}", error);
+    }
+}
+
+impl<'a> MetaCommand<'a> {
+    /// Parses the input following the `:`.
+    fn parse(input: &'a str) -> Result<Self, String> {
+        let (name, argument) = match input.trim().split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, argument.trim()),
+            None => (input.trim(), ""),
+        };
+        let expect_argument = |description: &str| {
+            if argument.is_empty() {
+                Err(format!("Missing {} after :{}", description, name))
+            } else {
+                Ok(argument)
+            }
+        };
+        let expect_none = |command| {
+            if argument.is_empty() {
+                Ok(command)
+            } else {
+                Err(format!(":{} takes no argument", name))
+            }
+        };
+
+        match name {
+            "dis" => expect_argument("expression").map(MetaCommand::Disassemble),
+            "globals" => expect_none(MetaCommand::Globals),
+            "load" => expect_argument("file").map(MetaCommand::Load),
+            "reset" => expect_none(MetaCommand::Reset),
+            "time" => expect_argument("expression").map(MetaCommand::Time),
+            "trace" => match argument {
+                "on" => Ok(MetaCommand::Trace(true)),
+                "off" => Ok(MetaCommand::Trace(false)),
+                _ => Err("Expected :trace on or :trace off".to_owned()),
+            },
+            "help" => expect_none(MetaCommand::Help),
+            _ => Err(format!("Unknown command :{}, see :help", name)),
+        }
+    }
+
+    fn execute(self, vm: &mut ReplVirtualMachine) {
+        match self {
+            MetaCommand::Disassemble(source) => match vm.compile(source) {
+                // compiling already printed the listing as debug output
+                Ok(_) if vm.debug() => {}
+                Ok(chunk) => print!("{}", chunk.disassemble(source)),
+                Err(error) => eprintln!("{:?}", Report::new(error)),
+            },
+            MetaCommand::Globals => {
+                let mut globals = vm.globals().collect::<Vec<_>>();
+                if globals.is_empty() {
+                    println!("No globals defined");
+                }
+                globals.sort_by_key(|(name, _)| *name);
+                for (name, value) in globals {
+                    println!("{} = {}", name, value);
+                }
+            }
+            MetaCommand::Load(path) => match fs::read_to_string(path) {
+                Ok(source) => interpret(&source, vm),
+                Err(error) => eprintln!("Could not read {}: {}", path, error),
+            },
+            MetaCommand::Reset => {
+                let tracer = vm.tracer_mut().take();
+                *vm = ReplVirtualMachine::with_tracer(vm.debug(), tracer);
+            }
+            MetaCommand::Time(source) => {
+                let start = Instant::now();
+                interpret(source, vm);
+                println!("Took {:?}", start.elapsed());
+            }
+            MetaCommand::Trace(enabled) => *vm.tracer_mut() = enabled.then(TextTracer::stdout),
+            MetaCommand::Help => println!("{}", HELP),
+        }
+    }
+}
+
+/// Reads lines until parentheses and braces are balanced. Returns `None` on
+/// Ctrl-D and an empty input on Ctrl-C.
+fn read_input(editor: &mut Editor<ReplHelper, FileHistory>) -> Result<Option<String>, ReadlineError> {
+    let mut input = String::new();
+    loop {
+        let prompt = if input.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+        match editor.readline(prompt) {
+            Ok(line) => {
+                input.push_str(&line);
+                if !is_incomplete(&input) {
+                    return Ok(Some(input));
+                }
+                input.push('\n');
+            }
+            Err(ReadlineError::Interrupted) => return Ok(Some(String::new())),
+            Err(ReadlineError::Eof) => return Ok(None),
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Whether `source` has unclosed parentheses, braces, strings or comments.
+fn is_incomplete(source: &str) -> bool {
+    let mut depth = 0;
+    for token in Scanner::new(source) {
+        match token {
+            Ok(token) => match token.tpe {
+                TokenType::LeftParen | TokenType::LeftBrace => depth += 1,
+                TokenType::RightParen | TokenType::RightBrace => depth -= 1,
+                _ => {}
+            },
+            Err(error) => {
+                return error
+                    .downcast_ref::<CompileError>()
+                    .is_some_and(|error| error.message().starts_with("Unterminated"))
+            }
+        }
+    }
+    depth > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_parse_meta_commands() {
+        assert_eq!(MetaCommand::parse("dis 1 + 2"), Ok(MetaCommand::Disassemble("1 + 2")));
+        assert_eq!(MetaCommand::parse("load  a.frox "), Ok(MetaCommand::Load("a.frox")));
+        assert_eq!(MetaCommand::parse("trace off"), Ok(MetaCommand::Trace(false)));
+        assert_eq!(MetaCommand::parse("reset"), Ok(MetaCommand::Reset));
+
+        assert_eq!(MetaCommand::parse("time"), Err("Missing expression after :time".to_owned()));
+        assert_eq!(MetaCommand::parse("globals 1"), Err(":globals takes no argument".to_owned()));
+        assert_eq!(MetaCommand::parse("trace"), Err("Expected :trace on or :trace off".to_owned()));
+        assert_eq!(MetaCommand::parse("quit"), Err("Unknown command :quit, see :help".to_owned()));
+        for name in META_COMMANDS {
+            assert!(!MetaCommand::parse(name).is_err_and(|error| error.starts_with("Unknown")));
+        }
+    }
+
+    #[test]
+    fn should_toggle_tracing() {
+        let mut vm = ReplVirtualMachine::with_tracer(false, None);
+
+        MetaCommand::Trace(true).execute(&mut vm);
+        assert!(vm.tracer_mut().is_some());
+        MetaCommand::Trace(false).execute(&mut vm);
+        assert!(vm.tracer_mut().is_none());
+    }
+
+    #[test]
+    fn should_reset_to_a_fresh_machine() {
+        let mut vm = ReplVirtualMachine::with_tracer(false, None);
+        vm.define_function("answer", || 42.0);
+        MetaCommand::Trace(true).execute(&mut vm);
+
+        MetaCommand::Reset.execute(&mut vm);
+        assert!(vm.global("answer").is_none());
+        assert!(vm.tracer_mut().is_some());
+        *vm.tracer_mut() = None;
+        assert_eq!(vm.interpret("1 + 2").unwrap().as_number().unwrap(), 3.0);
+    }
+
+    #[test]
+    fn should_detect_incomplete_input() {
+        assert!(is_incomplete("(1 +"));
+        assert!(is_incomplete("fun f() {\n  (1"));
+        assert!(is_incomplete("\"unterminated"));
+        assert!(is_incomplete("/* unterminated"));
+
+        assert!(!is_incomplete("(1 + 2)"));
+        assert!(!is_incomplete("1 + 2)"));
+        assert!(!is_incomplete("\"(\" // ("));
+        assert!(!is_incomplete("1 $ ("));
+    }
+}
@@ -0,0 +1,200 @@
+use std::borrow::Cow;
+
+use fast_frox::{
+    error::CompileError,
+    scanner::{is_identifier_char, Scanner, TokenType, KEYWORDS},
+};
+use rustyline::{
+    completion::Completer,
+    highlight::{CmdKind, Highlighter},
+    hint::Hinter,
+    validate::Validator,
+    Context, Helper,
+};
+
+use super::META_COMMANDS;
+
+const RESET: &str = "\x1b[0m";
+const DIMMED: &str = "\x1b[2m";
+const KEYWORD: &str = "\x1b[35;1m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[33m";
+const ERROR: &str = "\x1b[31;4m";
+const DIAGNOSTIC: &str = "\x1b[2;31m";
+
+/// Completes keywords, globals and meta-commands, highlights tokens and shows
+/// scan errors after the cursor.
+#[derive(Default)]
+pub(crate) struct ReplHelper {
+    /// Names of the globals defined when reading the input.
+    pub(crate) globals: Vec<String>,
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let before_cursor = &line[..pos];
+        if let Some(command) = before_cursor.strip_prefix(':') {
+            if !command.contains(char::is_whitespace) {
+                let candidates = META_COMMANDS.iter().filter(|name| name.starts_with(command));
+                return Ok((1, candidates.map(|name| name.to_string()).collect()));
+            }
+        }
+
+        let start = before_cursor
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !is_identifier_char(*c))
+            .map_or(0, |(index, c)| index + c.len_utf8());
+        let prefix = &before_cursor[start..];
+        if prefix.is_empty() {
+            return Ok((pos, Vec::new()));
+        }
+        let mut candidates = KEYWORDS
+            .iter()
+            .copied()
+            .chain(self.globals.iter().map(String::as_str))
+            .filter(|name| name.starts_with(prefix))
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        candidates.sort();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if pos < line.len() || line.starts_with(':') {
+            return None;
+        }
+        highlight_tokens(line).1.map(|message| format!("  {}", message))
+    }
+}
+
+impl Highlighter for ReplHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        if line.starts_with(':') {
+            return Cow::Borrowed(line);
+        }
+        Cow::Owned(highlight_tokens(line).0)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("{}{}{}", DIAGNOSTIC, hint, RESET))
+    }
+
+    fn highlight_char(&self, _line: &str, _pos: usize, _kind: CmdKind) -> bool {
+        true
+    }
+}
+
+impl Validator for ReplHelper {}
+
+impl Helper for ReplHelper {}
+
+/// Colors `line` by token type. Everything from the first scan error on is
+/// marked as error, its message is returned along.
+fn highlight_tokens(line: &str) -> (String, Option<String>) {
+    let mut highlighted = String::with_capacity(line.len());
+    let mut end = 0;
+    for token in Scanner::with_trivia(line) {
+        let token = match token {
+            Ok(token) => token,
+            Err(error) => {
+                let message = match error.downcast_ref::<CompileError>() {
+                    Some(error) => error.message().to_owned(),
+                    None => error.to_string(),
+                };
+                highlighted.push_str(ERROR);
+                highlighted.push_str(&line[end..]);
+                highlighted.push_str(RESET);
+                return (highlighted, Some(message));
+            }
+        };
+        let lexeme = token.lexeme(line);
+        let color = match token.tpe {
+            tpe if tpe.is_keyword() => Some(KEYWORD),
+            TokenType::String => Some(STRING),
+            TokenType::Number => Some(NUMBER),
+            TokenType::LineComment | TokenType::BlockComment => Some(DIMMED),
+            _ => None,
+        };
+        match color {
+            Some(color) => {
+                highlighted.push_str(color);
+                highlighted.push_str(lexeme);
+                highlighted.push_str(RESET);
+            }
+            None => highlighted.push_str(lexeme),
+        }
+        end = token.start + token.length;
+    }
+    (highlighted, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use rustyline::history::DefaultHistory;
+
+    use super::*;
+
+    fn complete_with(helper: &ReplHelper, line: &str) -> (usize, Vec<String>) {
+        let history = DefaultHistory::new();
+        helper.complete(line, line.len(), &Context::new(&history)).unwrap()
+    }
+
+    fn complete(line: &str) -> (usize, Vec<String>) {
+        complete_with(&ReplHelper::default(), line)
+    }
+
+    #[test]
+    fn should_complete_keywords() {
+        assert_eq!(complete("1 + f"), (4, vec!["false".to_owned(), "for".to_owned(), "fun".to_owned()]));
+        assert_eq!(complete("(tr"), (1, vec!["true".to_owned()]));
+        assert_eq!(complete("1 + "), (4, vec![]));
+        assert_eq!(complete("1 ×f"), (4, vec!["false".to_owned(), "for".to_owned(), "fun".to_owned()]));
+    }
+
+    #[test]
+    fn should_complete_globals() {
+        let helper = ReplHelper {
+            globals: vec!["clock".to_owned(), "format".to_owned()],
+        };
+
+        assert_eq!(complete_with(&helper, "cl"), (0, vec!["class".to_owned(), "clock".to_owned()]));
+        assert_eq!(complete_with(&helper, "1 + fo"), (4, vec!["for".to_owned(), "format".to_owned()]));
+
+        let helper = ReplHelper {
+            globals: vec!["my_clock".to_owned()],
+        };
+        assert_eq!(complete_with(&helper, "-my_c"), (1, vec!["my_clock".to_owned()]));
+    }
+
+    #[test]
+    fn should_complete_meta_commands() {
+        assert_eq!(complete(":t"), (1, vec!["time".to_owned(), "trace".to_owned()]));
+        assert_eq!(complete(":dis n"), (5, vec!["nil".to_owned()]));
+    }
+
+    #[test]
+    fn should_highlight_tokens() {
+        let (highlighted, error) = highlight_tokens("nil + 1 // one");
+
+        assert_eq!(
+            highlighted,
+            format!("{KEYWORD}nil{RESET} + {NUMBER}1{RESET} {DIMMED}// one{RESET}")
+        );
+        assert_eq!(error, None);
+    }
+
+    #[test]
+    fn should_mark_scan_errors() {
+        let (highlighted, error) = highlight_tokens("1 + \"one");
+
+        assert_eq!(highlighted, format!("{NUMBER}1{RESET} + {ERROR}\"one{RESET}"));
+        assert_eq!(error.as_deref(), Some("Unterminated string."));
+    }
+}
@@ -1,25 +1,43 @@
-use std::{iter::Enumerate, str::Chars};
 use miette::{Result, NamedSource, SourceSpan};
 
-use crate::{peek_peek_iterator::PeekPeekIterator, error::CompileError};
+use crate::error::CompileError;
 
-pub(crate) struct Scanner<'a> {
-    source_iterator: PeekPeekIterator<Enumerate<Chars<'a>>>,
+/// The reserved words recognized by `identifier_type`.
+pub const KEYWORDS: [&str; 16] = [
+    "and", "class", "else", "false", "for", "fun", "if", "nil", "or", "print", "return", "super",
+    "this", "true", "var", "while",
+];
+
+/// Splits Lox source code into tokens.
+///
+/// By default whitespace and comments are skipped. A scanner created with
+/// [`Scanner::with_trivia`] emits them as tokens as well, so that the lexemes of
+/// all tokens concatenate back to the original source.
+pub struct Scanner<'a> {
     source: &'a str,
+    bytes: &'a [u8],
+    current: usize,
+    trivia: bool,
+    finished: bool,
     line: usize,
+    line_start: usize,
     start: usize,
+    start_line: usize,
+    start_column: usize,
 }
 
-#[derive(Debug, PartialEq)]
-pub(crate) struct Token {
-    pub(crate) tpe: TokenType,
-    pub(crate) start: usize,
-    pub(crate) length: usize,
-    pub(crate) line: usize,
+/// A token with its byte span and the 1-based line and byte column it starts at.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Token {
+    pub tpe: TokenType,
+    pub start: usize,
+    pub length: usize,
+    pub line: usize,
+    pub column: usize,
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub(crate) enum TokenType {
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum TokenType {
     LeftParen,
     RightParen,
     LeftBrace,
@@ -62,252 +80,360 @@ pub(crate) enum TokenType {
     Var,
     While,
 
+    Whitespace,
+    LineComment,
+    BlockComment,
+
     Eof,
 }
 
 impl<'a> Scanner<'a> {
-    pub(crate) fn new(source: &'a str) -> Self {
+    pub fn new(source: &'a str) -> Self {
         Scanner {
-            source_iterator: PeekPeekIterator::new(source.chars().enumerate()),
             source,
+            bytes: source.as_bytes(),
+            current: 0,
+            trivia: false,
+            finished: false,
             line: 1,
+            line_start: 0,
             start: 0,
+            start_line: 1,
+            start_column: 1,
         }
     }
 
-    pub(crate) fn scan_token(&mut self) -> Result<Token> {
-        self.skip_whitespace();
-        let next_char = self.source_iterator.next();
-        match next_char {
-            None => return Ok(Token::new(TokenType::Eof, 0, 0, 0)),
-            Some((pos, c)) => {
-                self.start = pos;
-                return self.match_char(c);
-            }
-        };
+    pub fn with_trivia(source: &'a str) -> Self {
+        Scanner {
+            trivia: true,
+            ..Scanner::new(source)
+        }
+    }
 
-        // Err(CompileError {
-        //     msg: format!("Unexpected character `{:?}`", next_char),
-        //     src: NamedSource::new("", self.source.to_owned()),
-        //     span: (self.start, self.source_iterator.peek().map(|(pos, _)| pos - 1).unwrap_or(self.source.len())).into()
-        // }.into())
-    }
-
-    fn match_char(&mut self, c: char) -> Result<Token> {
-        match c {
-            '(' => self.token(TokenType::LeftParen),
-            ')' => self.token(TokenType::RightParen),
-            '{' => self.token(TokenType::LeftBrace),
-            '}' => self.token(TokenType::RightBrace),
-            ';' => self.token(TokenType::Semicolon),
-            ',' => self.token(TokenType::Comma),
-            '.' => self.token(TokenType::Dot),
-            '+' => self.token(TokenType::Plus),
-            '-' => self.token(TokenType::Minus),
-            '*' => self.token(TokenType::Star),
-            '/' => self.token(TokenType::Slash),
-            '!' => {
-                if self.match_token('=') {
-                    self.token(TokenType::BangEqual)
-                } else {
-                    self.token(TokenType::Bang)
-                }
+    /// Scans the next token. Once the source is exhausted every call returns an `Eof` token.
+    pub fn scan_token(&mut self) -> Result<Token> {
+        loop {
+            let token = self.scan_any_token()?;
+            if self.trivia || !token.tpe.is_trivia() {
+                return Ok(token);
             }
-            '=' => {
-                if self.match_token('=') {
-                    self.token(TokenType::EqualEqual)
+        }
+    }
+
+    fn scan_any_token(&mut self) -> Result<Token> {
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.current - self.line_start + 1;
+
+        let Some(byte) = self.advance() else {
+            return self.token(TokenType::Eof);
+        };
+        match byte {
+            b'(' => self.token(TokenType::LeftParen),
+            b')' => self.token(TokenType::RightParen),
+            b'{' => self.token(TokenType::LeftBrace),
+            b'}' => self.token(TokenType::RightBrace),
+            b';' => self.token(TokenType::Semicolon),
+            b',' => self.token(TokenType::Comma),
+            b'.' => self.token(TokenType::Dot),
+            b'+' => self.token(TokenType::Plus),
+            b'-' => self.token(TokenType::Minus),
+            b'*' => self.token(TokenType::Star),
+            b'/' => {
+                if self.match_byte(b'/') {
+                    self.line_comment()
+                } else if self.match_byte(b'*') {
+                    self.block_comment()
                 } else {
-                    self.token(TokenType::Equal)
+                    self.token(TokenType::Slash)
                 }
             }
-            '<' => {
-                if self.match_token('=') {
-                    self.token(TokenType::LessEqual)
-                } else {
-                    self.token(TokenType::Less)
-                }
+            b'!' => self.token_if_equal(TokenType::BangEqual, TokenType::Bang),
+            b'=' => self.token_if_equal(TokenType::EqualEqual, TokenType::Equal),
+            b'<' => self.token_if_equal(TokenType::LessEqual, TokenType::Less),
+            b'>' => self.token_if_equal(TokenType::GreaterEqual, TokenType::Greater),
+            b' ' | b'\r' | b'\t' | b'\n' => {
+                self.current -= 1;
+                self.whitespace()
             }
-            '>' => {
-                if self.match_token('=') {
-                    self.token(TokenType::GreaterEqual)
+            b'"' => self.string(),
+            b'0'..=b'9' => self.number(),
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' => self.identifier(),
+            byte if byte.is_ascii() => self.unexpected_character(byte as char),
+            _ => {
+                let c = self.source[self.start..]
+                    .chars()
+                    .next()
+                    .expect("Token should start on a char boundary");
+                self.current = self.start + c.len_utf8();
+                if c.is_alphabetic() {
+                    self.identifier()
                 } else {
-                    self.token(TokenType::Greater)
+                    self.unexpected_character(c)
                 }
             }
-            '"' => self.string(),
-            c if c.is_ascii_digit() => self.number(),
-            c if c.is_alphabetic() => self.identifier(),
-            _ => todo!(),
         }
     }
 
-    fn match_token(&mut self, expected: char) -> bool {
-        match self.source_iterator.peek() {
-            None => false,
-            Some((_, c)) if c == &expected => {
-                self.source_iterator.next();
-                true
+    fn unexpected_character(&self, c: char) -> Result<Token> {
+        Err(CompileError {
+            msg: format!("Unexpected character `{}`.", c),
+            src: NamedSource::new("", self.source.to_owned()),
+            span: (self.start, c.len_utf8()).into(),
+        }
+        .into())
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.current).copied()
+    }
+
+    #[inline]
+    fn peek_next(&self) -> Option<u8> {
+        self.bytes.get(self.current + 1).copied()
+    }
+
+    #[inline]
+    fn advance(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.current += 1;
+        Some(byte)
+    }
+
+    fn match_byte(&mut self, expected: u8) -> bool {
+        if self.peek() == Some(expected) {
+            self.current += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn newline(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
+    fn token(&self, tpe: TokenType) -> Result<Token> {
+        Ok(Token::new(
+            tpe,
+            self.start,
+            self.current - self.start,
+            self.start_line,
+            self.start_column,
+        ))
+    }
+
+    fn token_if_equal(&mut self, matched: TokenType, unmatched: TokenType) -> Result<Token> {
+        if self.match_byte(b'=') {
+            self.token(matched)
+        } else {
+            self.token(unmatched)
+        }
+    }
+
+    fn whitespace(&mut self) -> Result<Token> {
+        while let Some(byte) = self.peek() {
+            match byte {
+                b' ' | b'\r' | b'\t' => self.current += 1,
+                b'\n' => {
+                    self.current += 1;
+                    self.newline();
+                }
+                _ => break,
             }
-            _ => false,
         }
+        self.token(TokenType::Whitespace)
     }
 
-    fn token(&mut self, tpe: TokenType) -> Result<Token> {
-        let source_len = &self.source.len();
-        let head_position = self
-            .source_iterator
-            .peek()
-            .map(|(pos, _)| pos)
-            .unwrap_or(source_len);
-        Ok(Token::new(tpe, self.start, head_position - self.start, self.line))
+    fn line_comment(&mut self) -> Result<Token> {
+        self.current = self.bytes[self.current..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map(|length| self.current + length)
+            .unwrap_or(self.bytes.len());
+        self.token(TokenType::LineComment)
     }
 
-    fn skip_whitespace(&mut self) {
-        loop {
-            match self.source_iterator.peek() {
-                None => return,
-                Some((_, c)) => match c {
-                    ' ' | '\r' | '\t' => {
-                        self.source_iterator.next();
-                        ()
-                    }
-                    '\n' => {
-                        self.line += 1;
-                        self.source_iterator.next();
-                        ()
+    fn block_comment(&mut self) -> Result<Token> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.advance() {
+                None => {
+                    return Err(CompileError {
+                        msg: "Unterminated block comment.".to_owned(),
+                        src: NamedSource::new("", self.source.to_owned()),
+                        span: (self.start, 2).into(),
                     }
-                    '/' => match self.source_iterator.peek_peek() {
-                        None => return,
-                        Some((_, '/')) => loop {
-                            match self.source_iterator.next() {
-                                None | Some((_, '\n')) => break,
-                                _ => (),
-                            }
-                        },
-                        _ => return,
-                    },
-                    _ => return,
-                },
+                    .into())
+                }
+                Some(b'/') if self.match_byte(b'*') => depth += 1,
+                Some(b'*') if self.match_byte(b'/') => depth -= 1,
+                Some(b'\n') => self.newline(),
+                _ => (),
             }
         }
+        self.token(TokenType::BlockComment)
     }
 
     fn string(&mut self) -> Result<Token> {
         loop {
-            match self.source_iterator.peek() {
-                None => return Err(CompileError { 
-                    msg: "Unterminated string.".to_owned(),
-                    src: NamedSource::new("", self.source.to_owned()),
-                    span: (self.start, self.source.len()).into()
-                }.into()),
-                Some((_, '"')) => break,
-                Some((_, c)) => {
-                    if c == &'\n' {
-                        self.line += 1;
+            match self.advance() {
+                None => {
+                    return Err(CompileError {
+                        msg: "Unterminated string.".to_owned(),
+                        src: NamedSource::new("", self.source.to_owned()),
+                        span: (self.start, self.source.len()).into(),
                     }
-                    self.source_iterator.next();
-                    ()
+                    .into())
                 }
+                Some(b'"') => break,
+                Some(b'\n') => self.newline(),
+                _ => (),
             }
         }
 
-        self.source_iterator.next();
         self.token(TokenType::String)
     }
 
     fn number(&mut self) -> Result<Token> {
         self.consume_digits();
 
-        if let Some((_, '.')) = self.source_iterator.peek() {
-            match self.source_iterator.peek_peek() {
-                Some((_, c)) if c.is_ascii_digit() => {
-                    self.source_iterator.next();
-                    self.consume_digits();
-                }
-                _ => (),
-            }
+        if self.peek() == Some(b'.') && matches!(self.peek_next(), Some(b'0'..=b'9')) {
+            self.current += 1;
+            self.consume_digits();
         }
 
         self.token(TokenType::Number)
     }
 
     fn consume_digits(&mut self) {
-        loop {
-            match self.source_iterator.peek() {
-                None => break,
-                Some((_, c)) if !c.is_ascii_digit() => break,
-                _ => {
-                    self.source_iterator.next();
-                    ()
-                }
-            }
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.current += 1;
         }
     }
 
     fn identifier(&mut self) -> Result<Token> {
-        loop {
-            match self.source_iterator.peek() {
-                Some((_, c)) if c.is_ascii_digit() || c.is_alphabetic() => {
-                    self.source_iterator.next();
-                    ()
+        while let Some(byte) = self.peek() {
+            if byte.is_ascii_alphanumeric() || byte == b'_' {
+                self.current += 1;
+            } else if byte.is_ascii() {
+                break;
+            } else {
+                let c = self.source[self.current..]
+                    .chars()
+                    .next()
+                    .expect("Identifier should continue on a char boundary");
+                if !is_identifier_char(c) {
+                    break;
                 }
-                _ => break,
+                self.current += c.len_utf8();
             }
         }
 
-        let current_pos = self
-            .source_iterator
-            .peek()
-            .map(|(pos, _)| pos - 1)
-            .unwrap_or(self.source.len());
-        self.token(self.identifier_type(current_pos))
-    }
-
-    // TODO: implement with trie
-    fn identifier_type(&self, current_pos: usize) -> TokenType {
-        let slice = &self.source[self.start..current_pos];
-        match slice {
-            "and" => TokenType::And,
-            "class" => TokenType::Class,
-            "else" => TokenType::Else,
-            "false" => TokenType::False,
-            "for" => TokenType::For,
-            "fun" => TokenType::Fun,
-            "if" => TokenType::If,
-            "nil" => TokenType::Nil,
-            "or" => TokenType::Or,
-            "print" => TokenType::Print,
-            "return" => TokenType::Return,
-            "super" => TokenType::Super,
-            "this" => TokenType::This,
-            "true" => TokenType::True,
-            "var" => TokenType::Var,
-            "while" => TokenType::While,
+        self.token(Scanner::identifier_type(&self.bytes[self.start..self.current]))
+    }
+
+    // Hand-rolled trie over the first bytes of the lexeme, so that at most one
+    // keyword is compared against any identifier.
+    fn identifier_type(lexeme: &[u8]) -> TokenType {
+        match lexeme {
+            [b'a', rest @ ..] => Scanner::check_keyword(rest, b"nd", TokenType::And),
+            [b'c', rest @ ..] => Scanner::check_keyword(rest, b"lass", TokenType::Class),
+            [b'e', rest @ ..] => Scanner::check_keyword(rest, b"lse", TokenType::Else),
+            [b'f', b'a', rest @ ..] => Scanner::check_keyword(rest, b"lse", TokenType::False),
+            [b'f', b'o', rest @ ..] => Scanner::check_keyword(rest, b"r", TokenType::For),
+            [b'f', b'u', rest @ ..] => Scanner::check_keyword(rest, b"n", TokenType::Fun),
+            [b'i', rest @ ..] => Scanner::check_keyword(rest, b"f", TokenType::If),
+            [b'n', rest @ ..] => Scanner::check_keyword(rest, b"il", TokenType::Nil),
+            [b'o', rest @ ..] => Scanner::check_keyword(rest, b"r", TokenType::Or),
+            [b'p', rest @ ..] => Scanner::check_keyword(rest, b"rint", TokenType::Print),
+            [b'r', rest @ ..] => Scanner::check_keyword(rest, b"eturn", TokenType::Return),
+            [b's', rest @ ..] => Scanner::check_keyword(rest, b"uper", TokenType::Super),
+            [b't', b'h', rest @ ..] => Scanner::check_keyword(rest, b"is", TokenType::This),
+            [b't', b'r', rest @ ..] => Scanner::check_keyword(rest, b"ue", TokenType::True),
+            [b'v', rest @ ..] => Scanner::check_keyword(rest, b"ar", TokenType::Var),
+            [b'w', rest @ ..] => Scanner::check_keyword(rest, b"hile", TokenType::While),
             _ => TokenType::Identifier,
         }
     }
+
+    #[inline]
+    fn check_keyword(rest: &[u8], expected: &[u8], tpe: TokenType) -> TokenType {
+        if rest == expected {
+            tpe
+        } else {
+            TokenType::Identifier
+        }
+    }
 }
 
+/// Yields all tokens up to and including `Eof`, or up to the first error.
 impl<'a> Iterator for Scanner<'a> {
     type Item = Result<Token>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.scan_token() {
-            // Ok(Token { tpe: TokenType::Eof, .. }) => None,
-            token => Some(token)
+        if self.finished {
+            return None;
         }
+        let token = self.scan_token();
+        self.finished = !matches!(token, Ok(Token { tpe, .. }) if tpe != TokenType::Eof);
+        Some(token)
     }
 }
 
 impl Token {
-    pub(crate) fn new(tpe: TokenType, start: usize, length: usize, line: usize) -> Self {
+    pub fn new(tpe: TokenType, start: usize, length: usize, line: usize, column: usize) -> Self {
         Token {
             tpe,
             start,
             length,
             line,
+            column,
         }
     }
+
+    pub fn lexeme<'a>(&self, source: &'a str) -> &'a str {
+        &source[self.start..self.start + self.length]
+    }
+}
+
+/// Whether `c` may continue an identifier. Identifiers start with the same
+/// characters except digits.
+pub fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || (!c.is_ascii() && c.is_alphabetic())
+}
+
+impl TokenType {
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self,
+            TokenType::Whitespace | TokenType::LineComment | TokenType::BlockComment
+        )
+    }
+
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenType::And
+                | TokenType::Class
+                | TokenType::Else
+                | TokenType::False
+                | TokenType::For
+                | TokenType::Fun
+                | TokenType::If
+                | TokenType::Nil
+                | TokenType::Or
+                | TokenType::Print
+                | TokenType::Return
+                | TokenType::Super
+                | TokenType::This
+                | TokenType::True
+                | TokenType::Var
+                | TokenType::While
+        )
+    }
 }
 
 impl From<Token> for SourceSpan {
@@ -319,12 +445,24 @@ impl From<Token> for SourceSpan {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn should_list_all_keywords() {
+        let mut types = std::collections::HashSet::new();
+        for keyword in KEYWORDS {
+            let tpe = Scanner::identifier_type(keyword.as_bytes());
+            assert!(tpe.is_keyword(), "{} scans as {:?}", keyword, tpe);
+            types.insert(tpe);
+        }
+        assert_eq!(types.len(), KEYWORDS.len());
+    }
 
     #[test]
     fn should_scan_digit() {
         let mut scanner = Scanner::new("1337.42");
         let token = scanner.scan_token().unwrap();
-        assert_eq!(token, Token::new(TokenType::Number, 0, 7, 1));
+        assert_eq!(token, Token::new(TokenType::Number, 0, 7, 1, 1));
     }
 
     #[test]
@@ -353,6 +491,30 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn should_ignore_nested_block_comment() {
+        let mut scanner = Scanner::new("/* outer /* inner */\n still comment */ +");
+        let token = scanner.scan_token().unwrap();
+        assert_eq!(token, Token::new(TokenType::Plus, 39, 1, 2, 19));
+    }
+
+    #[test]
+    fn should_count_lines_after_line_comment() {
+        let mut scanner = Scanner::new("// foo\n+");
+        let token = scanner.scan_token().unwrap();
+        assert_eq!(token.line, 2);
+    }
+
+    #[test]
+    fn should_fail_on_unterminated_block_comment() {
+        let mut scanner = Scanner::new("+ /* /* */");
+        scanner.scan_token().unwrap();
+        let error = scanner.scan_token().unwrap_err();
+        let compile_error = error.downcast_ref::<CompileError>().unwrap();
+        assert_eq!(compile_error.msg, "Unterminated block comment.");
+        assert_eq!(compile_error.span, (2, 2).into());
+    }
+
     #[test]
     fn should_scan_keyword() {
         let mut scanner = Scanner::new("while");
@@ -365,4 +527,130 @@ mod tests {
             }
         ));
     }
+
+    #[test]
+    fn should_only_match_whole_keywords() {
+        let source = "fo for form th this thiss t";
+        let types = Scanner::new(source)
+            .map(|token| token.unwrap().tpe)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Identifier,
+                TokenType::For,
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::This,
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::Eof,
+            ]
+        );
+    }
+
+    #[test]
+    fn should_scan_underscores_in_identifiers() {
+        let lexemes = Scanner::new("_ _private snake_case for_")
+            .map(|token| token.unwrap())
+            .filter(|token| token.tpe == TokenType::Identifier)
+            .map(|token| token.lexeme("_ _private snake_case for_"))
+            .collect::<Vec<_>>();
+        assert_eq!(lexemes, ["_", "_private", "snake_case", "for_"]);
+    }
+
+    #[test]
+    fn should_place_eof_at_end_of_source() {
+        let tokens = Scanner::new("(1 +\n  2").collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(
+            tokens.last().unwrap(),
+            &Token::new(TokenType::Eof, 8, 0, 2, 4)
+        );
+    }
+
+    #[test]
+    fn should_emit_trivia_tokens() {
+        let source = "1 // one\n/* two */+";
+        let tokens = Scanner::with_trivia(source)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        let types = tokens.iter().map(|token| token.tpe).collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Number,
+                TokenType::Whitespace,
+                TokenType::LineComment,
+                TokenType::Whitespace,
+                TokenType::BlockComment,
+                TokenType::Plus,
+                TokenType::Eof,
+            ]
+        );
+        assert_eq!(tokens[5], Token::new(TokenType::Plus, 18, 1, 2, 10));
+    }
+
+    #[test]
+    fn should_use_byte_offsets_for_non_ascii_source() {
+        let source = "äöü + 1";
+        let tokens = Scanner::new(source).collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(tokens[0].lexeme(source), "äöü");
+        assert_eq!(tokens[1], Token::new(TokenType::Plus, 7, 1, 1, 8));
+    }
+
+    #[test]
+    fn should_fail_on_unexpected_character() {
+        let mut scanner = Scanner::new("#");
+        assert!(scanner.scan_token().is_err());
+    }
+
+    fn source_piece() -> impl Strategy<Value = String> {
+        prop_oneof![
+            "[a-zA-Z_äß][a-zA-Z0-9_]{0,8}",
+            "[0-9]{1,5}(\\.[0-9]{1,3})?",
+            "\"[^\"]{0,10}\"",
+            "(and|class|else|false|for|fun|if|nil|or|print|return|super|this|true|var|while)",
+            "(\\(|\\)|\\{|\\}|;|,|\\.|-|\\+|\\*|/|!|!=|=|==|<|<=|>|>=)",
+            "[ \t\r\n]{1,3}",
+            "//[^\n]{0,10}\n",
+            "/\\*[^*/]{0,10}\\*/",
+            "/\\*[^*/]{0,5}/\\*[^*/]{0,5}\\*/[^*/]{0,5}\\*/",
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn should_reproduce_source_from_token_lexemes(pieces in proptest::collection::vec(source_piece(), 0..40)) {
+            let source = pieces.join(" ");
+            let tokens = Scanner::with_trivia(&source).collect::<Result<Vec<_>>>().unwrap();
+
+            let mut position = 0;
+            let mut reproduced = String::new();
+            for token in tokens.iter().filter(|token| token.tpe != TokenType::Eof) {
+                prop_assert_eq!(token.start, position);
+                position += token.length;
+                reproduced.push_str(token.lexeme(&source));
+            }
+            prop_assert_eq!(reproduced, source);
+        }
+
+        #[test]
+        fn should_reproduce_any_source_that_scans(source in any::<String>()) {
+            if let Ok(tokens) = Scanner::with_trivia(&source).collect::<Result<Vec<_>>>() {
+                let reproduced = tokens.iter().map(|token| token.lexeme(&source)).collect::<String>();
+                prop_assert_eq!(reproduced, source);
+            }
+        }
+
+        #[test]
+        fn should_report_line_and_column_of_token_start(pieces in proptest::collection::vec(source_piece(), 0..40)) {
+            let source = pieces.join(" ");
+            for token in Scanner::with_trivia(&source).map(Result::unwrap).filter(|token| token.tpe != TokenType::Eof) {
+                let preceding = &source[..token.start];
+                let line_start = preceding.rfind('\n').map(|pos| pos + 1).unwrap_or(0);
+                prop_assert_eq!(token.line, preceding.matches('\n').count() + 1);
+                prop_assert_eq!(token.column, token.start - line_start + 1);
+            }
+        }
+    }
 }
@@ -0,0 +1,197 @@
+use std::io::{self, Write};
+
+use crate::{
+    debug::{write_json_value, Format, Instruction},
+    value::Value,
+};
+
+/// Observes execution, the VM calls it before every instruction.
+pub trait Tracer {
+    /// When `false` the VM does not even decode instructions for the tracer,
+    /// so [`NoTracer`] compiles to nothing.
+    const ENABLED: bool = true;
+
+    fn trace(&mut self, instruction: &Instruction, stack: &[Value]);
+}
+
+pub struct NoTracer;
+
+impl Tracer for NoTracer {
+    const ENABLED: bool = false;
+
+    #[inline(always)]
+    fn trace(&mut self, _instruction: &Instruction, _stack: &[Value]) {}
+}
+
+/// Traces while `Some`, for switching tracing on and off at runtime.
+impl<T: Tracer> Tracer for Option<T> {
+    const ENABLED: bool = T::ENABLED;
+
+    fn trace(&mut self, instruction: &Instruction, stack: &[Value]) {
+        if let Some(tracer) = self {
+            tracer.trace(instruction, stack);
+        }
+    }
+}
+
+/// Prints the stack followed by the instruction about to be executed, in the
+/// format of the disassembly listing.
+pub struct TextTracer<W: Write> {
+    out: W,
+    previous_line: Option<usize>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TextTracer<W> {
+    pub fn new(out: W) -> Self {
+        TextTracer {
+            out,
+            previous_line: None,
+            error: None,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// The first error writing the trace, nothing is written after it.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+}
+
+impl TextTracer<io::Stdout> {
+    pub fn stdout() -> Self {
+        TextTracer::new(io::stdout())
+    }
+}
+
+impl<W: Write> Tracer for TextTracer<W> {
+    fn trace(&mut self, instruction: &Instruction, stack: &[Value]) {
+        let mut line = String::from("          ");
+        for value in stack {
+            line.push_str(&format!("[ {} ]", value));
+        }
+        line.push('\n');
+        instruction
+            .render(Format::Text, self.previous_line, &mut line)
+            .expect("Writing into a String cannot fail");
+        self.previous_line = Some(instruction.line);
+
+        write_line(&mut self.out, &line, &mut self.error);
+    }
+}
+
+/// Writes one JSON object per instruction, holding the disassembled
+/// instruction and the stack as an array.
+pub struct JsonLinesTracer<W: Write> {
+    out: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonLinesTracer<W> {
+    pub fn new(out: W) -> Self {
+        JsonLinesTracer { out, error: None }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// The first error writing the trace, nothing is written after it.
+    pub fn take_error(&mut self) -> Option<io::Error> {
+        self.error.take()
+    }
+}
+
+impl<W: Write> Tracer for JsonLinesTracer<W> {
+    fn trace(&mut self, instruction: &Instruction, stack: &[Value]) {
+        let mut line = String::from("{");
+        let mut write = || {
+            instruction.write_json_fields(&mut line)?;
+            line.push_str(",\"stack\":[");
+            for (index, value) in stack.iter().enumerate() {
+                if index > 0 {
+                    line.push(',');
+                }
+                write_json_value(*value, &mut line)?;
+            }
+            line.push_str("]}");
+            std::fmt::Result::Ok(())
+        };
+        write().expect("Writing into a String cannot fail");
+
+        write_line(&mut self.out, &line, &mut self.error);
+    }
+}
+
+/// Writes `line` unless an earlier write failed, keeping the first error so
+/// that a closed pipe does not abort the traced execution.
+fn write_line(out: &mut impl Write, line: &str, error: &mut Option<io::Error>) {
+    if error.is_none() {
+        if let Err(failure) = writeln!(out, "{}", line) {
+            *error = Some(failure);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_machine::{testing::ClosedPipe, VirtualMachine};
+
+    fn trace<T: Tracer>(source: &str, tracer: T) -> T {
+        let mut vm = VirtualMachine::with_tracer(false, tracer);
+        vm.interpret(source).unwrap();
+        vm.into_tracer()
+    }
+
+    #[test]
+    fn should_trace_stack_and_instructions_as_text() {
+        let out = trace("-1.5 *\n 2", TextTracer::new(Vec::new())).into_inner();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap().lines().collect::<Vec<_>>(),
+            vec![
+                "          ",
+                "0000    1 OP_CONSTANT    0 1.5",
+                "          [ 1.5 ]",
+                "0002    | OP_NEGATE",
+                "          [ -1.5 ]",
+                "0003    2 OP_CONSTANT    1 2",
+                "          [ -1.5 ][ 2 ]",
+                "0005    | OP_MULTIPLY",
+                "          [ -3 ]",
+                "0006    | OP_RETURN",
+            ]
+        );
+    }
+
+    #[test]
+    fn should_keep_first_write_error() {
+        let mut tracer = trace("1 + 2", TextTracer::new(ClosedPipe::default()));
+        assert_eq!(tracer.take_error().map(|error| error.kind()), Some(io::ErrorKind::BrokenPipe));
+        assert_eq!(tracer.into_inner().writes(), 1);
+
+        let mut tracer = trace("1 + 2", JsonLinesTracer::new(ClosedPipe::default()));
+        assert_eq!(tracer.take_error().map(|error| error.kind()), Some(io::ErrorKind::BrokenPipe));
+        assert!(tracer.take_error().is_none());
+    }
+
+    #[test]
+    fn should_trace_json_lines() {
+        let out = trace("1 + 2", JsonLinesTracer::new(Vec::new())).into_inner();
+        let lines = String::from_utf8(out).unwrap();
+
+        assert_eq!(
+            lines.lines().collect::<Vec<_>>(),
+            vec![
+                r#"{"offset":0,"line":1,"column":1,"op_code":"OP_CONSTANT","operand":0,"constant":1,"stack":[]}"#,
+                r#"{"offset":2,"line":1,"column":5,"op_code":"OP_CONSTANT","operand":1,"constant":2,"stack":[1]}"#,
+                r#"{"offset":4,"line":1,"column":5,"op_code":"OP_ADD","stack":[1,2]}"#,
+                r#"{"offset":5,"line":1,"column":6,"op_code":"OP_RETURN","stack":[3]}"#,
+            ]
+        );
+    }
+}
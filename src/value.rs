@@ -1,94 +1,100 @@
-// pub type Value = f64;
+use crate::error::ValueError;
 
-use std::fmt::Display;
-use miette::Result;
+#[cfg(not(feature = "nan-boxing"))]
+mod tagged;
+#[cfg(not(feature = "nan-boxing"))]
+pub use tagged::Value;
 
-use crate::error::RuntimeError;
+#[cfg(feature = "nan-boxing")]
+mod nan_boxed;
+#[cfg(feature = "nan-boxing")]
+pub use nan_boxed::Value;
 
-#[derive(Clone, Copy)]
-pub enum Value {
-    Boolean(bool),
-    Number(f64),
-    Nil,
-}
+mod conversion;
+pub use conversion::{FromValue, IntoValue};
 
-impl Display for Value {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Boolean(boolean) => f.write_fmt(format_args!("{}", boolean)),
-            Self::Number(number) => f.write_fmt(format_args!("{}", number)),
-            Self::Nil => f.write_str("nil"),
-        }
-    }
-}
+/// Handle of a native function registered with a virtual machine. It is only
+/// meaningful to the machine that handed it out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NativeId(pub(crate) u32);
 
 impl Value {
-    pub fn is_number(self) -> bool {
-        match self {
-            Value::Number(_) => true,
-            _ => false
-        }
-    }
-
-    pub fn as_number(self) -> Result<f64> {
-        match self {
-            Value::Number(num) => Ok(num),
-            _ => Err(RuntimeError::new(format!("Cannot cast {} as number", self)).into())
-        }
+    pub fn as_number(self) -> Result<f64, ValueError> {
+        self.number().ok_or(ValueError::NotANumber(self))
     }
 }
 
 impl std::ops::Neg for Value {
-    type Output=Result<Value>;
+    type Output=Result<Value, ValueError>;
 
     fn neg(self) -> Self::Output {
-        match self {
-            Value::Number(number) => Ok(Value::Number(-number)),
-            _ => Err(RuntimeError::new(format!("Unable to negate {}, operand must be a number", self)).into())
+        match self.number() {
+            Some(number) => Ok(Value::Number(-number)),
+            _ => Err(ValueError::NegateOperand(self))
         }
     }
 }
 
 impl std::ops::Add for Value {
-    type Output=Result<Value>;
+    type Output=Result<Value, ValueError>;
 
     fn add(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Value::Number(lhs_value), Value::Number(rhs_value)) => Ok(Value::Number(lhs_value + rhs_value)),
-            _ => Err(RuntimeError::new(format!("Unable to add {} and {}, operands must be numbers", self, rhs)).into())
+        match (self.number(), rhs.number()) {
+            (Some(lhs_value), Some(rhs_value)) => Ok(Value::Number(lhs_value + rhs_value)),
+            _ => Err(ValueError::NumberOperands { operation: "add", lhs: self, rhs })
         }
     }
 }
 
 impl std::ops::Sub for Value {
-    type Output=Result<Value>;
+    type Output=Result<Value, ValueError>;
 
     fn sub(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Value::Number(lhs_value), Value::Number(rhs_value)) => Ok(Value::Number(lhs_value - rhs_value)),
-            _ => Err(RuntimeError::new(format!("Unable to subtract {} and {}, operands must be numbers", self, rhs)).into())
+        match (self.number(), rhs.number()) {
+            (Some(lhs_value), Some(rhs_value)) => Ok(Value::Number(lhs_value - rhs_value)),
+            _ => Err(ValueError::NumberOperands { operation: "subtract", lhs: self, rhs })
         }
     }
 }
 
 impl std::ops::Mul for Value {
-    type Output=Result<Value>;
+    type Output=Result<Value, ValueError>;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Value::Number(lhs_value), Value::Number(rhs_value)) => Ok(Value::Number(lhs_value * rhs_value)),
-            _ => Err(RuntimeError::new(format!("Unable to multiuply {} and {}, operands must be numbers", self, rhs)).into())
+        match (self.number(), rhs.number()) {
+            (Some(lhs_value), Some(rhs_value)) => Ok(Value::Number(lhs_value * rhs_value)),
+            _ => Err(ValueError::NumberOperands { operation: "multiply", lhs: self, rhs })
         }
     }
 }
 
 impl std::ops::Div for Value {
-    type Output=Result<Value>;
+    type Output=Result<Value, ValueError>;
 
     fn div(self, rhs: Self) -> Self::Output {
-        match (self, rhs) {
-            (Value::Number(lhs_value), Value::Number(rhs_value)) => Ok(Value::Number(lhs_value / rhs_value)),
-            _ => Err(RuntimeError::new(format!("Unable to divide {} and {}, operands must be numbers", self, rhs)).into())
+        match (self.number(), rhs.number()) {
+            (Some(lhs_value), Some(rhs_value)) => Ok(Value::Number(lhs_value / rhs_value)),
+            _ => Err(ValueError::NumberOperands { operation: "divide", lhs: self, rhs })
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_describe_invalid_operands() {
+        let error = (Value::Number(1.0) * Value::Boolean(true)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unable to multiply 1 and true, operands must be numbers"
+        );
+    }
+
+    #[test]
+    fn should_describe_invalid_cast() {
+        let error = Value::Nil.as_number().unwrap_err();
+        assert_eq!(error.to_string(), "Cannot cast nil as number");
+    }
+}
@@ -0,0 +1,183 @@
+//! Conversions between script values and Rust types.
+//!
+//! Numbers, booleans and `nil` have Rust counterparts: `f64`, `f32` and the
+//! integer types, `bool`, `()` and `Option<T>`.
+
+use crate::error::ConversionError;
+
+use super::Value;
+
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, ConversionError>;
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Nil
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        value.boolean().ok_or(ConversionError {
+            expected: "boolean",
+            found: value,
+        })
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Number(self)
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        value.number().ok_or(ConversionError {
+            expected: "number",
+            found: value,
+        })
+    }
+}
+
+impl IntoValue for f32 {
+    fn into_value(self) -> Value {
+        Value::Number(self as f64)
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        f64::from_value(value).map(|number| number as f32)
+    }
+}
+
+/// Integers beyond 2^53 lose precision as numbers. Numbers only convert back
+/// if they are integral and within the range of the type.
+macro_rules! impl_integer {
+    ($($integer:ty),*) => {
+        $(
+            impl IntoValue for $integer {
+                fn into_value(self) -> Value {
+                    Value::Number(self as f64)
+                }
+            }
+
+            impl FromValue for $integer {
+                fn from_value(value: Value) -> Result<Self, ConversionError> {
+                    let error = ConversionError {
+                        expected: stringify!($integer),
+                        found: value,
+                    };
+                    let number = value.number().ok_or(error)?;
+                    // `MAX as f64` rounds up for 64 bit integers, hence the exclusive bound
+                    let in_range = number >= <$integer>::MIN as f64 && number < <$integer>::MAX as f64 + 1.0;
+                    if number.fract() == 0.0 && in_range {
+                        Ok(number as $integer)
+                    } else {
+                        Err(error)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+/// `None` is `nil`.
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        match self {
+            Some(value) => value.into_value(),
+            None => Value::Nil,
+        }
+    }
+}
+
+/// `nil` is `None`, anything else must convert into `T`.
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, ConversionError> {
+        if value.is_nil() {
+            Ok(None)
+        } else {
+            T::from_value(value).map(Some)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtual_machine::VirtualMachine;
+
+    #[test]
+    fn should_round_trip_primitives() {
+        assert_eq!(f64::from_value(1.5.into_value()).unwrap(), 1.5);
+        assert!(bool::from_value(true.into_value()).unwrap());
+        assert_eq!(i32::from_value((-7).into_value()).unwrap(), -7);
+        assert_eq!(u64::from_value(u32::MAX.into_value()).unwrap(), u32::MAX as u64);
+        assert!(().into_value().is_nil());
+    }
+
+    #[test]
+    fn should_map_nil_to_none() {
+        assert_eq!(Option::<f64>::from_value(Value::Nil).unwrap(), None);
+        assert_eq!(Option::<f64>::from_value(Value::Number(2.0)).unwrap(), Some(2.0));
+        assert!(None::<bool>.into_value().is_nil());
+        assert_eq!(Some(3u8).into_value().number(), Some(3.0));
+    }
+
+    #[test]
+    fn should_describe_failed_conversions() {
+        fn error<T: FromValue>(value: Value) -> String {
+            T::from_value(value).err().unwrap().to_string()
+        }
+
+        assert_eq!(error::<f64>(Value::Nil), "expected number, got nil");
+        assert_eq!(error::<bool>(Value::Number(0.0)), "expected boolean, got 0");
+        assert_eq!(error::<Option<bool>>(Value::Number(1.0)), "expected boolean, got 1");
+        assert_eq!(error::<i32>(Value::Number(1.5)), "expected i32, got 1.5");
+        assert_eq!(error::<u8>(Value::Number(256.0)), "expected u8, got 256");
+        assert_eq!(error::<u8>(Value::Number(-1.0)), "expected u8, got -1");
+        assert_eq!(error::<i64>(Value::Number(2f64.powi(63))), "expected i64, got 9223372036854776000");
+        assert_eq!(error::<usize>(Value::Number(f64::NAN)), "expected usize, got NaN");
+    }
+
+    #[test]
+    fn should_convert_arguments_of_defined_functions() {
+        let mut vm = VirtualMachine::new(false);
+        vm.define_function("repeat", |times: u32, enabled: Option<bool>| {
+            enabled.unwrap_or(true).then_some(times)
+        });
+        let error = |vm: &mut VirtualMachine, arguments: &[Value]| vm.call("repeat", arguments).unwrap_err().to_string();
+
+        assert_eq!(vm.call("repeat", &[Value::Number(3.0), Value::Nil]).unwrap().number(), Some(3.0));
+        assert!(vm.call("repeat", &[Value::Number(3.0), Value::Boolean(false)]).unwrap().is_nil());
+        assert_eq!(error(&mut vm, &[Value::Number(-3.0), Value::Nil]), "repeat: argument 1 expected u32, got -3");
+        assert_eq!(error(&mut vm, &[Value::Number(3.0), Value::Number(0.0)]), "repeat: argument 2 expected boolean, got 0");
+    }
+}
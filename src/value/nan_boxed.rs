@@ -0,0 +1,157 @@
+use std::fmt::{Debug, Display};
+
+use super::NativeId;
+
+// Every value is a single u64. Anything that is not a quiet NaN with all `QNAN`
+// bits set is a plain f64. Singletons use the low bits of the quiet NaN as tag,
+// native functions set `TAG_NATIVE` and keep their id in the low 32 bits. The
+// sign bit is reserved for tagging heap pointers.
+const QNAN: u64 = 0x7ffc_0000_0000_0000;
+const SIGN_BIT: u64 = 0x8000_0000_0000_0000;
+const TAG_NIL: u64 = 1;
+const TAG_FALSE: u64 = 2;
+const TAG_TRUE: u64 = 3;
+const TAG_NATIVE: u64 = 1 << 32;
+
+const NIL: u64 = QNAN | TAG_NIL;
+const FALSE: u64 = QNAN | TAG_FALSE;
+const TRUE: u64 = QNAN | TAG_TRUE;
+
+#[derive(Clone, Copy)]
+pub struct Value(u64);
+
+// The constructors mirror those of the tagged layout, so that both layouts
+// can be used interchangeably by the rest of the crate.
+#[allow(non_snake_case, non_upper_case_globals)]
+impl Value {
+    pub const Nil: Value = Value(NIL);
+
+    pub fn Number(number: f64) -> Value {
+        if number.is_nan() {
+            // canonicalize, a NaN payload must never look like a tagged value
+            Value(f64::NAN.to_bits())
+        } else {
+            Value(number.to_bits())
+        }
+    }
+
+    pub fn Boolean(boolean: bool) -> Value {
+        if boolean {
+            Value(TRUE)
+        } else {
+            Value(FALSE)
+        }
+    }
+
+    pub fn Native(id: NativeId) -> Value {
+        Value(QNAN | TAG_NATIVE | id.0 as u64)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            NIL => f.write_str("nil"),
+            TRUE => f.write_str("true"),
+            FALSE => f.write_str("false"),
+            _ if self.native().is_some() => f.write_str("<native fn>"),
+            bits => f.write_fmt(format_args!("{}", f64::from_bits(bits))),
+        }
+    }
+}
+
+impl Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.number(), self.native()) {
+            (Some(number), _) => f.debug_tuple("Number").field(&number).finish(),
+            (_, Some(id)) => f.debug_tuple("Native").field(&id).finish(),
+            _ if self.0 == NIL => f.write_str("Nil"),
+            _ => f.debug_tuple("Boolean").field(&(self.0 == TRUE)).finish(),
+        }
+    }
+}
+
+impl Value {
+    pub fn is_number(self) -> bool {
+        self.0 & QNAN != QNAN
+    }
+
+    pub fn is_nil(self) -> bool {
+        self.0 == NIL
+    }
+
+    #[inline]
+    pub(crate) fn number(self) -> Option<f64> {
+        if self.is_number() {
+            Some(f64::from_bits(self.0))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn boolean(self) -> Option<bool> {
+        match self.0 {
+            TRUE => Some(true),
+            FALSE => Some(false),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn native(self) -> Option<NativeId> {
+        if self.0 & (SIGN_BIT | QNAN | TAG_NATIVE) == QNAN | TAG_NATIVE {
+            Some(NativeId(self.0 as u32))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_fit_into_a_single_word() {
+        assert_eq!(std::mem::size_of::<Value>(), 8);
+    }
+
+    #[test]
+    fn should_round_trip_numbers() {
+        for number in [0.0, -0.0, 1.5, -42.0, f64::INFINITY, f64::MIN_POSITIVE] {
+            let value = Value::Number(number);
+            assert!(value.is_number());
+            assert_eq!(value.as_number().unwrap().to_bits(), number.to_bits());
+        }
+    }
+
+    #[test]
+    fn should_keep_nan_a_number() {
+        let payload_nan = f64::from_bits(QNAN | TAG_TRUE);
+        assert!(Value::Number(payload_nan).is_number());
+        assert!(Value::Number(f64::NAN).as_number().unwrap().is_nan());
+    }
+
+    #[test]
+    fn should_distinguish_singletons() {
+        assert!(!Value::Nil.is_number());
+        assert!(!Value::Boolean(true).is_number());
+        assert_eq!(Value::Boolean(true).to_string(), "true");
+        assert_eq!(Value::Boolean(false).to_string(), "false");
+        assert_eq!(Value::Nil.to_string(), "nil");
+        assert_eq!(Value::Number(2.5).to_string(), "2.5");
+    }
+
+    #[test]
+    fn should_keep_native_ids() {
+        for id in [0, 1, 3, u32::MAX] {
+            let value = Value::Native(NativeId(id));
+            assert!(!value.is_number());
+            assert_eq!(value.native(), Some(NativeId(id)));
+            assert_eq!(value.boolean(), None);
+            assert!(!value.is_nil());
+        }
+        assert_eq!(Value::Nil.native(), None);
+        assert_eq!(Value::Number(f64::NAN).native(), None);
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt::{Debug, Display};
+
+use super::NativeId;
+
+#[derive(Clone, Copy)]
+enum Repr {
+    Boolean(bool),
+    Number(f64),
+    Nil,
+    Native(NativeId),
+}
+
+#[derive(Clone, Copy)]
+pub struct Value(Repr);
+
+// The constructors are named like the variants they wrap, matching the
+// NaN-boxed layout so that both can be used interchangeably.
+#[allow(non_snake_case, non_upper_case_globals)]
+impl Value {
+    pub const Nil: Value = Value(Repr::Nil);
+
+    pub fn Number(number: f64) -> Value {
+        Value(Repr::Number(number))
+    }
+
+    pub fn Boolean(boolean: bool) -> Value {
+        Value(Repr::Boolean(boolean))
+    }
+
+    pub fn Native(id: NativeId) -> Value {
+        Value(Repr::Native(id))
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Repr::Boolean(boolean) => f.write_fmt(format_args!("{}", boolean)),
+            Repr::Number(number) => f.write_fmt(format_args!("{}", number)),
+            Repr::Nil => f.write_str("nil"),
+            Repr::Native(_) => f.write_str("<native fn>"),
+        }
+    }
+}
+
+impl Debug for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Repr::Boolean(boolean) => f.debug_tuple("Boolean").field(&boolean).finish(),
+            Repr::Number(number) => f.debug_tuple("Number").field(&number).finish(),
+            Repr::Nil => f.write_str("Nil"),
+            Repr::Native(id) => f.debug_tuple("Native").field(&id).finish(),
+        }
+    }
+}
+
+impl Value {
+    pub fn is_number(self) -> bool {
+        matches!(self.0, Repr::Number(_))
+    }
+
+    pub fn is_nil(self) -> bool {
+        matches!(self.0, Repr::Nil)
+    }
+
+    #[inline]
+    pub(crate) fn number(self) -> Option<f64> {
+        match self.0 {
+            Repr::Number(num) => Some(num),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn boolean(self) -> Option<bool> {
+        match self.0 {
+            Repr::Boolean(boolean) => Some(boolean),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn native(self) -> Option<NativeId> {
+        match self.0 {
+            Repr::Native(id) => Some(id),
+            _ => None,
+        }
+    }
+}
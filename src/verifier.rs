@@ -0,0 +1,203 @@
+use crate::{
+    chunk::{read_long_operand, Chunk},
+    error::VerifierError,
+    op_code::OpCode,
+    virtual_machine::STACK_MAX,
+    InstructionSize,
+};
+
+/// Checks that `chunk` can be executed without any runtime checks on the bytecode.
+///
+/// Every instruction must be a known op code with all of its operands present,
+/// constant and name indices must point into their tables, the stack must neither
+/// underflow nor outgrow the VM stack, and the code must end with `OpReturn`.
+/// Chunks from untrusted sources have to pass this before they are executed.
+///
+/// Returns the most values the chunk keeps on the stack at once.
+pub fn verify(chunk: &Chunk) -> Result<usize, VerifierError> {
+    let instructions = decode(chunk)?;
+    let max_depth = verify_stack_depth(&instructions)?;
+
+    match instructions.last() {
+        Some((_, OpCode::OpReturn, _)) => Ok(max_depth),
+        _ => Err(VerifierError::MissingReturn),
+    }
+}
+
+/// Splits the code into instructions and checks their operands. Instructions
+/// come with their offset and their operand, or 0 if they have none.
+fn decode(chunk: &Chunk) -> Result<Vec<(usize, OpCode, usize)>, VerifierError> {
+    let code = &chunk.code;
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+    while offset < code.len() {
+        let byte = code[offset];
+        let instruction = OpCode::try_from(&byte)
+            .map_err(|_| VerifierError::UnknownOpCode { offset, byte })?;
+        if offset + instruction.size() > code.len() {
+            return Err(VerifierError::TruncatedInstruction { offset });
+        }
+
+        let operand = match instruction.size() {
+            4 => read_long_operand(&code[offset + 1..]),
+            2 => code[offset + 1] as usize,
+            _ => 0,
+        };
+        match instruction {
+            OpCode::OpConstant | OpCode::OpConstantLong if operand >= chunk.constants.len() => {
+                return Err(VerifierError::ConstantOutOfBounds { offset, index: operand });
+            }
+            OpCode::OpGetGlobal if operand >= chunk.names.len() => {
+                return Err(VerifierError::NameOutOfBounds { offset, index: operand });
+            }
+            _ => {}
+        }
+
+        instructions.push((offset, instruction, operand));
+        offset += instruction.size();
+    }
+    Ok(instructions)
+}
+
+// There are no jumps yet, so control flow is straight-line code up to the
+// first `OpReturn` and anything after it is unreachable.
+fn verify_stack_depth(instructions: &[(usize, OpCode, usize)]) -> Result<usize, VerifierError> {
+    let mut depth: usize = 0;
+    let mut max_depth = 0;
+    for &(offset, instruction, operand) in instructions {
+        let (pops, pushes) = stack_effect(instruction, operand);
+        depth = depth
+            .checked_sub(pops)
+            .ok_or(VerifierError::StackUnderflow { offset })?
+            + pushes;
+        if depth > STACK_MAX {
+            return Err(VerifierError::StackOverflow { offset });
+        }
+        max_depth = max_depth.max(depth);
+        if instruction == OpCode::OpReturn {
+            break;
+        }
+    }
+    Ok(max_depth)
+}
+
+/// Number of values an instruction pops and pushes.
+fn stack_effect(instruction: OpCode, operand: usize) -> (usize, usize) {
+    match instruction {
+        OpCode::OpReturn => (1, 0),
+        OpCode::OpConstant
+        | OpCode::OpConstantLong
+        | OpCode::OpNil
+        | OpCode::OpTrue
+        | OpCode::OpFalse
+        | OpCode::OpGetGlobal => (0, 1),
+        OpCode::OpNegate => (1, 1),
+        OpCode::OpAdd | OpCode::OpSubtract | OpCode::OpMultiply | OpCode::OpDivide => (2, 1),
+        // the callee and its arguments are replaced by the result
+        OpCode::OpCall => (operand + 1, 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    fn chunk_of(code: &[u8]) -> Chunk {
+        let mut chunk = Chunk::new();
+        chunk.add_constant(Value::Nil);
+        for byte in code {
+            chunk.write_chunk(*byte, 1, 1);
+        }
+        chunk
+    }
+
+    #[test]
+    fn should_accept_valid_chunk() {
+        let chunk = chunk_of(&[
+            OpCode::OpConstant as u8,
+            0,
+            OpCode::OpTrue as u8,
+            OpCode::OpAdd as u8,
+            OpCode::OpReturn as u8,
+        ]);
+        assert_eq!(verify(&chunk).unwrap(), 2);
+    }
+
+    #[test]
+    fn should_reject_unknown_op_code() {
+        let chunk = chunk_of(&[OpCode::OpNil as u8, 42, OpCode::OpReturn as u8]);
+        assert!(matches!(
+            verify(&chunk),
+            Err(VerifierError::UnknownOpCode { offset: 1, byte: 42 })
+        ));
+    }
+
+    #[test]
+    fn should_reject_missing_operands_and_constants() {
+        let chunk = chunk_of(&[OpCode::OpConstant as u8, 3, OpCode::OpReturn as u8]);
+        assert!(matches!(
+            verify(&chunk),
+            Err(VerifierError::ConstantOutOfBounds { offset: 0, index: 3 })
+        ));
+
+        let chunk = chunk_of(&[OpCode::OpConstantLong as u8, 0]);
+        assert!(matches!(
+            verify(&chunk),
+            Err(VerifierError::TruncatedInstruction { offset: 0 })
+        ));
+    }
+
+    #[test]
+    fn should_reject_missing_names() {
+        let mut chunk = chunk_of(&[OpCode::OpGetGlobal as u8, 1, OpCode::OpReturn as u8]);
+        chunk.add_name("clock");
+        assert!(matches!(
+            verify(&chunk),
+            Err(VerifierError::NameOutOfBounds { offset: 0, index: 1 })
+        ));
+    }
+
+    #[test]
+    fn should_pop_callee_and_arguments_on_call() {
+        let call = |arguments| {
+            chunk_of(&[
+                OpCode::OpNil as u8,
+                OpCode::OpTrue as u8,
+                OpCode::OpCall as u8,
+                arguments,
+                OpCode::OpReturn as u8,
+            ])
+        };
+        assert!(verify(&call(1)).is_ok());
+        assert!(matches!(
+            verify(&call(2)),
+            Err(VerifierError::StackUnderflow { offset: 2 })
+        ));
+    }
+
+    #[test]
+    fn should_reject_stack_underflow() {
+        let chunk = chunk_of(&[OpCode::OpNil as u8, OpCode::OpAdd as u8, OpCode::OpReturn as u8]);
+        assert!(matches!(
+            verify(&chunk),
+            Err(VerifierError::StackUnderflow { offset: 1 })
+        ));
+    }
+
+    #[test]
+    fn should_reject_stack_overflow() {
+        let mut code = vec![OpCode::OpNil as u8; STACK_MAX + 1];
+        code.push(OpCode::OpReturn as u8);
+        assert!(matches!(
+            verify(&chunk_of(&code)),
+            Err(VerifierError::StackOverflow { offset }) if offset == STACK_MAX
+        ));
+    }
+
+    #[test]
+    fn should_require_return_at_end() {
+        let chunk = chunk_of(&[OpCode::OpNil as u8]);
+        assert!(matches!(verify(&chunk), Err(VerifierError::MissingReturn)));
+    }
+}
@@ -1,136 +1,528 @@
-use std::mem::size_of;
+mod config;
 
-use crate::{chunk::Chunk, debug::ChunkDebug, op_code::OpCode, value::Value, compiler::{Compiler, Parser}, error::RuntimeError};
-use miette::Result;
+use std::{
+    collections::HashMap,
+    io::Write,
+    ops::ControlFlow,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
-pub struct VirtualMachine {
-    stack: [Value; 256],
-    stack_top: *mut Value,
+use crate::{chunk::{read_long_operand, Chunk}, debug::ChunkDebug, op_code::OpCode, value::{NativeId, Value}, compiler::Compiler, verifier, error::{CompileError, ExecutionError, Frame, InterpretError, NativeError, RuntimeError, ValueError}, native::{Native, TypedNative}, tracer::{NoTracer, Tracer}, InstructionSize};
+
+pub use config::VmConfig;
+
+pub(crate) const STACK_MAX: usize = 256;
+
+/// Instructions executed between checks of the deadline and interrupts.
+const CHECK_INTERVAL: u64 = 1024;
+
+pub struct VirtualMachine<T: Tracer = NoTracer> {
+    stack: [Value; STACK_MAX],
+    /// Index of the next free slot. An index rather than a pointer, so that
+    /// the machine can be moved or replaced by a native in the middle of a call.
+    stack_top: usize,
     debug: bool,
+    output: Box<dyn Write>,
+    debug_output: Box<dyn Write>,
+    error: Option<ExecutionError>,
+    tracer: T,
+    globals: HashMap<String, Value>,
+    natives: Vec<Native<T>>,
+    /// Number of executions in progress, more than one while natives execute code.
+    executions: usize,
+    /// Fuel not yet handed out to `budget`, `None` for unlimited.
+    fuel: Option<u64>,
+    /// Instructions left until the limits are checked again.
+    budget: u64,
+    deadline: Option<Instant>,
+    interrupt: Arc<AtomicBool>,
 }
 
-struct InstructionPointer {
-    ptr: *const u8,
+/// Stops the execution in progress of a [`VirtualMachine`] from any thread.
+#[derive(Debug, Clone)]
+pub struct InterruptHandle {
+    interrupt: Arc<AtomicBool>,
 }
 
-pub enum InterpretResult {
-    Ok,
-    CompileError,
-    RuntimeError,
+impl InterruptHandle {
+    /// Makes the running code fail with [`ExecutionError::Interrupted`]
+    /// within a few instructions. Does nothing if no code is running.
+    pub fn interrupt(&self) {
+        self.interrupt.store(true, Ordering::Relaxed);
+    }
+}
+
+type Flow = ControlFlow<()>;
+
+struct InstructionPointer {
+    ptr: *const u8,
 }
 
 impl VirtualMachine {
+    /// A machine printing to stdout, see [`VmConfig`] for other destinations.
     pub fn new(debug: bool) -> Self {
-        let mut stack = [Value::Nil; 256];
+        VirtualMachine::with_tracer(debug, NoTracer)
+    }
+}
+
+impl<T: Tracer> VirtualMachine<T> {
+    pub fn with_tracer(debug: bool, tracer: T) -> Self {
+        VmConfig::new().debug(debug).build_with_tracer(tracer)
+    }
+
+    fn from_config(config: VmConfig, tracer: T) -> Self {
         VirtualMachine {
-            stack,
-            stack_top: stack.as_mut_ptr(),
-            debug,
+            stack: [Value::Nil; STACK_MAX],
+            stack_top: 0,
+            debug: config.debug,
+            output: config.output,
+            debug_output: config.debug_output,
+            error: None,
+            tracer,
+            globals: HashMap::new(),
+            natives: Vec::new(),
+            executions: 0,
+            fuel: None,
+            budget: 0,
+            deadline: None,
+            interrupt: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Whether compiled chunks are disassembled to the debug output.
+    pub fn debug(&self) -> bool {
+        self.debug
+    }
+
+    pub fn tracer_mut(&mut self) -> &mut T {
+        &mut self.tracer
+    }
+
+    pub fn into_tracer(self) -> T {
+        self.tracer
+    }
+
+    /// Discards everything left behind by previous executions. Globals
+    /// defined by the host are kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics when called by a native, the stack still belongs to its caller.
+    pub fn reset(&mut self) {
+        assert_eq!(self.executions, 0, "Cannot reset the machine while it is executing");
+        self.reset_stack();
+    }
+
+    /// Makes `function` callable from scripts as the global `name`, replacing
+    /// any previous global of that name.
+    ///
+    /// Natives may execute code on the machine they are handed, for example
+    /// with [`Self::call`].
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, function: F)
+    where
+        F: Fn(&mut VirtualMachine<T>, &[Value]) -> Result<Value, NativeError> + 'static,
+    {
+        let id = NativeId(self.natives.len() as u32);
+        self.natives.push(Native {
+            name: name.to_owned(),
+            arity,
+            function: Rc::new(function),
+        });
+        self.globals.insert(name.to_owned(), Value::Native(id));
+    }
+
+    /// Defines a native from a Rust function with typed parameters. Arguments
+    /// that do not convert fail the call, like `argument 2 expected number, got nil`.
+    pub fn define_function<F, Args>(&mut self, name: &str, function: F)
+    where
+        F: TypedNative<Args> + 'static,
+    {
+        self.define_native(name, F::ARITY, move |_, arguments| function.call(arguments));
+    }
+
+    /// Calls the global function `name`, from the host or from within a native.
+    pub fn call(&mut self, name: &str, arguments: &[Value]) -> Result<Value, RuntimeError> {
+        let callee = self.global(name).ok_or_else(|| RuntimeError {
+            kind: ExecutionError::UndefinedGlobal(name.to_owned()),
+            trace: Vec::new(),
+        })?;
+        self.enter();
+        let result = self.call_value(callee, arguments);
+        self.executions -= 1;
+        result.map_err(|kind| locate(kind, None))
+    }
+
+    fn call_value(&mut self, callee: Value, arguments: &[Value]) -> Result<Value, ExecutionError> {
+        let index = match callee.native() {
+            Some(id) if (id.0 as usize) < self.natives.len() => id.0 as usize,
+            _ => return Err(ExecutionError::NotCallable(callee)),
+        };
+        let native = &self.natives[index];
+        if native.arity != arguments.len() {
+            return Err(ExecutionError::Arity {
+                name: native.name.clone(),
+                expected: native.arity,
+                found: arguments.len(),
+            });
+        }
+
+        let function = Rc::clone(&native.function);
+        function(self, arguments).map_err(|error| ExecutionError::Native {
+            name: self.natives[index].name.clone(),
+            error,
+        })
+    }
+
+    pub fn global(&self, name: &str) -> Option<Value> {
+        self.globals.get(name).copied()
+    }
+
+    pub fn globals(&self) -> impl Iterator<Item = (&str, Value)> {
+        self.globals.iter().map(|(name, value)| (name.as_str(), *value))
+    }
+
+    /// Limits the number of instructions executed from now on. Once it is
+    /// used up, execution fails with [`ExecutionError::OutOfFuel`] until more
+    /// fuel is set. `None` removes the limit.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+        self.budget = 0;
+    }
+
+    /// Fuel left, `None` if unlimited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel.map(|fuel| fuel + self.budget)
+    }
+
+    /// Execution past `deadline` fails with [`ExecutionError::DeadlineExceeded`].
+    /// `None` removes the deadline.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.return_budget();
+        self.deadline = deadline;
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle {
+            interrupt: Arc::clone(&self.interrupt),
+        }
+    }
+
+    /// Forces a check of the limits before the next instruction.
+    fn return_budget(&mut self) {
+        if let Some(fuel) = &mut self.fuel {
+            *fuel += self.budget;
         }
+        self.budget = 0;
+    }
+
+    /// Checks fuel, deadline and interrupts, then grants the next budget.
+    #[cold]
+    #[inline(never)]
+    fn refuel(&mut self) -> Result<(), ExecutionError> {
+        if self.interrupt.swap(false, Ordering::Relaxed) {
+            return Err(ExecutionError::Interrupted);
+        }
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(ExecutionError::DeadlineExceeded);
+        }
+        self.budget = match &mut self.fuel {
+            Some(0) => return Err(ExecutionError::OutOfFuel),
+            Some(fuel) => {
+                let budget = (*fuel).min(CHECK_INTERVAL);
+                *fuel -= budget;
+                budget
+            }
+            None => CHECK_INTERVAL,
+        };
+        Ok(())
+    }
+
+    /// Values currently on the stack, bottom first.
+    pub fn stack(&self) -> &[Value] {
+        &self.stack[..self.stack_top]
+    }
+
+    fn reset_stack(&mut self) {
+        self.stack_top = 0;
     }
 
-    pub fn init(&mut self) {
-        self.stack_top = self.stack.as_mut_ptr();
+    /// Compiles and executes `source`, returning the value of the expression.
+    /// Unless called from within a native, the value is also written to the output.
+    pub fn interpret(&mut self, source: &str) -> Result<Value, InterpretError> {
+        let chunk = self.compile(source)?;
+        let nested = self.executions > 0;
+        let result = self.execute(&chunk)?;
+        if !nested {
+            writeln!(self.output, "{}", result).map_err(InterpretError::Output)?;
+        }
+        Ok(result)
     }
 
-    pub fn interpret(&mut self, source: &str) -> Result<()> {
+    pub fn compile(&mut self, source: &str) -> Result<Chunk, InterpretError> {
         let mut chunk = Chunk::new();
-        let parser = Parser::default();
-        let mut compiler = Compiler::new(parser, source, &mut chunk, self.debug);
+        let mut compiler = Compiler::new(source, &mut chunk);
+
+        compiler.compile().map_err(|report| {
+            report
+                .downcast::<CompileError>()
+                .expect("The compiler should only report compile errors")
+        })?;
+        if self.debug {
+            write!(self.debug_output, "{}", chunk.disassemble("code")).map_err(InterpretError::Output)?;
+        }
+        chunk.verify()?;
+        Ok(chunk)
+    }
 
-        compiler.compile()?;
+    /// Executes `chunk` on top of the current stack, which is left as found.
+    /// Natives use this to run code while their caller waits on the stack.
+    pub fn execute(&mut self, chunk: &Chunk) -> Result<Value, InterpretError> {
+        let max_stack_depth = if chunk.is_verified() {
+            chunk.max_stack_depth()
+        } else {
+            verifier::verify(chunk)?
+        };
+        // The verifier bounds the depth of the chunk alone, not of the values below it
+        if self.stack().len() + max_stack_depth > STACK_MAX {
+            let (line, column) = chunk.get_position(0).expect("Verified chunks end with a return");
+            let error = RuntimeError {
+                kind: ExecutionError::StackOverflow,
+                trace: vec![Frame { offset: 0, line, column }],
+            };
+            return Err(error.into());
+        }
 
+        let base = self.stack_top;
         let mut ip = InstructionPointer::new(&chunk.code);
+        self.enter();
+        let result = self.run(&mut ip, chunk);
+        self.executions -= 1;
+        self.stack_top = base;
+        result.map_err(|error| self.runtime_error(error, &ip, chunk).into())
+    }
 
-        self.run(&mut ip, &chunk).map_err(|err| self.runtime_error(err.to_string(), &ip, &chunk).into())
+    /// Starts an execution or a call, which are nested when natives run code.
+    fn enter(&mut self) {
+        if self.executions == 0 {
+            // Interrupts aimed at an earlier execution must not stop this one
+            self.interrupt.store(false, Ordering::Relaxed);
+            self.return_budget();
+        }
+        self.executions += 1;
     }
 
-    fn run(&mut self, ip: &mut InstructionPointer, chunk: &Chunk) -> Result<()> {
+    /// Executes verified code. Every byte in instruction position is a known op
+    /// code, operands are in bounds, the stack stays within `STACK_MAX` and the
+    /// code ends with `OpReturn`. Hence neither decoding nor the stack need checks
+    /// and the match compiles to a single jump into the inlined handlers.
+    fn run(&mut self, ip: &mut InstructionPointer, chunk: &Chunk) -> Result<Value, ExecutionError> {
+        // Kept local to stay in a register, synced around calls which may execute code
+        let mut budget = self.budget;
         loop {
-            if self.debug {
-                self.debug(&ip, chunk);
+            if budget == 0 {
+                self.budget = 0;
+                self.refuel()?;
+                budget = self.budget;
             }
+            budget -= 1;
 
-            let instruction: OpCode = match (&ip.next()).try_into() {
-                Ok(instruction) => instruction,
-                Err(error) => return Err(RuntimeError { msg: error }.into()),
-            };
-            match instruction {
-                OpCode::OpReturn => {
-                    println!("{}", self.pop());
-                    return Ok(());
-                }
-                OpCode::OpConstant => {
-                    let constant_index = ip.next();
-                    let constant_value = chunk.constants.get(constant_index as usize).unwrap();
-                    self.push(*constant_value);
+            if T::ENABLED {
+                self.trace(ip, chunk);
+            }
+
+            // SAFETY: the chunk has been verified before execution
+            let instruction = unsafe { OpCode::from_byte_unchecked(ip.next()) };
+            let flow = match instruction {
+                OpCode::OpReturn => self.op_return(),
+                OpCode::OpConstant => self.op_constant(ip, chunk),
+                OpCode::OpConstantLong => self.op_constant_long(ip, chunk),
+                OpCode::OpNegate => self.op_negate(),
+                OpCode::OpAdd => self.binary_operation(|lhs, rhs| lhs + rhs, std::ops::Add::add),
+                OpCode::OpSubtract => self.binary_operation(|lhs, rhs| lhs - rhs, std::ops::Sub::sub),
+                OpCode::OpMultiply => self.binary_operation(|lhs, rhs| lhs * rhs, std::ops::Mul::mul),
+                OpCode::OpDivide => self.binary_operation(|lhs, rhs| lhs / rhs, std::ops::Div::div),
+                OpCode::OpTrue => self.push_value(Value::Boolean(true)),
+                OpCode::OpFalse => self.push_value(Value::Boolean(false)),
+                OpCode::OpNil => self.push_value(Value::Nil),
+                OpCode::OpGetGlobal => self.op_get_global(ip, chunk),
+                OpCode::OpCall => {
+                    self.budget = budget;
+                    let flow = self.op_call(ip);
+                    budget = self.budget;
+                    flow
                 }
-                OpCode::OpNegate => unsafe {
-                    let addr = self.stack_top.sub(1);
-                    *addr = (-*addr)?;
-                },
-                OpCode::OpAdd => self.binary_operation(std::ops::Add::add)?,
-                OpCode::OpSubtract => self.binary_operation(std::ops::Sub::sub)?,
-                OpCode::OpMultiply => self.binary_operation(std::ops::Mul::mul)?,
-                OpCode::OpDivide => self.binary_operation(std::ops::Div::div)?,
-                OpCode::OpTrue => self.push(Value::Boolean(true)),
-                OpCode::OpFalse => self.push(Value::Boolean(false)),
-                OpCode::OpNil => self.push(Value::Nil)
+            };
+            if let ControlFlow::Break(()) = flow {
+                self.budget = budget;
+                return match self.error.take() {
+                    Some(error) => Err(error),
+                    None => Ok(self.pop()),
+                };
             }
         }
     }
 
-    fn push(&mut self, value: Value) {
-        unsafe {
-            *self.stack_top = value;
-            self.stack_top = self.stack_top.add(1);
-        }
+    /// Leaves the result on the stack for [`Self::run`] to return.
+    #[inline(always)]
+    fn op_return(&mut self) -> Flow {
+        ControlFlow::Break(())
     }
 
-    fn pop(&mut self) -> Value {
-        unsafe {
-            self.stack_top = self.stack_top.sub(1);
-            *self.stack_top
+    #[inline(always)]
+    fn op_constant(&mut self, ip: &mut InstructionPointer, chunk: &Chunk) -> Flow {
+        let constant_index = ip.next() as usize;
+        // SAFETY: constant indices have been verified before execution
+        let constant_value = unsafe { *chunk.constants.get_unchecked(constant_index) };
+        self.push(constant_value);
+        ControlFlow::Continue(())
+    }
+
+    #[inline(always)]
+    fn op_constant_long(&mut self, ip: &mut InstructionPointer, chunk: &Chunk) -> Flow {
+        let constant_index = ip.next_long();
+        // SAFETY: constant indices have been verified before execution
+        let constant_value = unsafe { *chunk.constants.get_unchecked(constant_index) };
+        self.push(constant_value);
+        ControlFlow::Continue(())
+    }
+
+    #[inline(always)]
+    fn op_negate(&mut self) -> Flow {
+        let operand = self.peek(0);
+        self.stack[self.stack_top - 1] = match operand.number() {
+            Some(number) => Value::Number(-number),
+            None => match -operand {
+                Ok(value) => value,
+                Err(error) => return self.fail(error.into()),
+            },
+        };
+        ControlFlow::Continue(())
+    }
+
+    fn op_get_global(&mut self, ip: &mut InstructionPointer, chunk: &Chunk) -> Flow {
+        // SAFETY: name indices have been verified before execution
+        let name = unsafe { chunk.names.get_unchecked(ip.next() as usize) };
+        match self.globals.get(name) {
+            Some(value) => self.push_value(*value),
+            None => self.fail(ExecutionError::UndefinedGlobal(name.clone())),
         }
     }
 
-    fn peek(&mut self, distance: usize) -> Value {
-        unsafe {
-            *self.stack_top.sub(distance + 1)
+    fn op_call(&mut self, ip: &mut InstructionPointer) -> Flow {
+        let argument_count = ip.next() as usize;
+        let callee = self.peek(argument_count);
+        // Copied, natives may execute code which pushes onto the stack
+        let stack = self.stack();
+        let arguments = stack[stack.len() - argument_count..].to_vec();
+        match self.call_value(callee, &arguments) {
+            Ok(result) => {
+                // Nested executions leave the callee and arguments as they found them
+                self.stack_top -= argument_count + 1;
+                self.push_value(result)
+            }
+            Err(error) => self.fail(error),
         }
     }
 
-    fn binary_operation<Op: FnOnce(Value, Value) -> Result<Value>>(&mut self, op: Op) -> Result<()> {
+    /// Stops execution, the error is picked up by the dispatch loop.
+    #[cold]
+    fn fail(&mut self, error: ExecutionError) -> Flow {
+        self.error = Some(error);
+        ControlFlow::Break(())
+    }
+
+    #[inline(always)]
+    fn push_value(&mut self, value: Value) -> Flow {
+        self.push(value);
+        ControlFlow::Continue(())
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack[self.stack_top] = value;
+        self.stack_top += 1;
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack_top -= 1;
+        self.stack[self.stack_top]
+    }
+
+    fn peek(&self, distance: usize) -> Value {
+        self.stack[self.stack_top - 1 - distance]
+    }
+
+    /// Applies `number_op` directly when both operands are numbers and only
+    /// falls back to the generic `op` otherwise.
+    #[inline(always)]
+    fn binary_operation<NumberOp, Op>(&mut self, number_op: NumberOp, op: Op) -> Flow
+    where
+        NumberOp: FnOnce(f64, f64) -> f64,
+        Op: FnOnce(Value, Value) -> Result<Value, ValueError>,
+    {
         let rhs = self.peek(0);
         let lhs = self.peek(1);
-        let result = op(lhs, rhs)?;
-        self.pop();
-        self.pop();
-        self.push(result);
-        Ok(())
+        let result = match (lhs.number(), rhs.number()) {
+            (Some(lhs_value), Some(rhs_value)) => Value::Number(number_op(lhs_value, rhs_value)),
+            _ => match op(lhs, rhs) {
+                Ok(result) => result,
+                Err(error) => return self.fail(error.into()),
+            },
+        };
+        self.stack_top -= 1;
+        self.stack[self.stack_top - 1] = result;
+        ControlFlow::Continue(())
     }
 
-    fn runtime_error(&self, message: String, ip: &InstructionPointer, chunk: &Chunk) -> RuntimeError {
-        let offset = ip.address() - chunk.code.as_ptr() as usize - 1;
-        let line = chunk.get_line(offset);
-        let message = format!("{}\n[{}] {}", message, offset, line);
-        RuntimeError { msg: message }
+    fn runtime_error(&self, kind: ExecutionError, ip: &InstructionPointer, chunk: &Chunk) -> RuntimeError {
+        // Limits stop before an instruction, other failures happen after reading
+        // it entirely and their kind tells how far to go back
+        let read = match kind {
+            _ if kind.is_limit() => 0,
+            ExecutionError::Value(_) => OpCode::OpReturn.size(),
+            ExecutionError::UndefinedGlobal(_) => OpCode::OpGetGlobal.size(),
+            _ => OpCode::OpCall.size(),
+        };
+        let offset = ip.address() - chunk.code.as_ptr() as usize - read;
+        let (line, column) = chunk
+            .get_position(offset)
+            .expect("Failing instructions lie within the code");
+        locate(kind, Some(Frame { offset, line, column }))
     }
 
-    fn debug(&self, ip: &InstructionPointer, chunk: &Chunk) {
-        for slot_address in
-            (self.stack.as_ptr() as usize..self.stack_top as usize).step_by(size_of::<f64>())
-        {
-            let slot_value = unsafe { *(slot_address as *const f64) };
-            println!("[{}]", slot_value)
-        }
+    fn trace(&mut self, ip: &InstructionPointer, chunk: &Chunk) {
         let offset = ip.address() - chunk.code.as_ptr() as usize;
-        chunk.disassemble_instruction(offset);
+        let instruction = chunk.disassemble_instruction(offset);
+        let depth = self.stack().len();
+        self.tracer.trace(&instruction, &self.stack[..depth]);
     }
 }
 
-impl Drop for VirtualMachine {
-    fn drop(&mut self) {}
+/// Builds the error of a failure at `frame`. Failures of code run by a native
+/// keep their frames in front, limits they hit are lifted out of the native's
+/// error as they stop every execution.
+fn locate(kind: ExecutionError, frame: Option<Frame>) -> RuntimeError {
+    let (kind, mut trace) = match kind {
+        ExecutionError::Native {
+            error: NativeError::Runtime(nested),
+            ..
+        } if nested.kind.is_limit() => (nested.kind, nested.trace),
+        ExecutionError::Native {
+            error: NativeError::Runtime(nested),
+            name,
+        } => {
+            let trace = nested.trace.clone();
+            let error = NativeError::Runtime(nested);
+            (ExecutionError::Native { name, error }, trace)
+        }
+        kind => (kind, Vec::new()),
+    };
+    trace.extend(frame);
+    RuntimeError { kind, trace }
 }
 
 impl InstructionPointer {
@@ -146,14 +538,107 @@ impl InstructionPointer {
         }
     }
 
+    fn next_long(&mut self) -> usize {
+        unsafe {
+            let operand = read_long_operand(std::slice::from_raw_parts(self.ptr, 3));
+            self.ptr = self.ptr.add(3);
+            operand
+        }
+    }
+
     fn address(&self) -> usize {
         self.ptr as usize
     }
 }
 
+/// Helpers for tests looking at what scripts print, enabled by the `testing` feature.
+#[cfg(any(test, feature = "testing"))]
+pub mod testing {
+    use std::{cell::RefCell, io};
+
+    use super::*;
+
+    /// A sink that can still be read after handing it to a machine.
+    #[derive(Clone, Default)]
+    pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedBuffer {
+        pub fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).expect("Output should be UTF-8")
+        }
+    }
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A sink failing every write, like a pipe whose reader has gone away.
+    #[derive(Default)]
+    pub struct ClosedPipe {
+        writes: usize,
+    }
+
+    impl ClosedPipe {
+        /// How many writes were attempted.
+        pub fn writes(&self) -> usize {
+            self.writes
+        }
+    }
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Interprets `source` and returns what it printed.
+    pub fn interpret_captured(source: &str) -> Result<String, InterpretError> {
+        let output = SharedBuffer::default();
+        let mut vm = VmConfig::new().output(output.clone()).build();
+        vm.interpret(source)?;
+        Ok(output.contents())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn compile(source: &str) -> Chunk {
+        VirtualMachine::new(false).compile(source).expect("Source should compile")
+    }
+
+    /// Compares everything a chunk holds but its verification. Columns can be
+    /// skipped for chunks assembled from listings, which only carry lines.
+    #[cfg(test)]
+    pub(crate) fn assert_same_chunk(actual: &Chunk, expected: &Chunk, compare_columns: bool) {
+        assert_eq!(actual.code, expected.code);
+        let constants = |chunk: &Chunk| chunk.constants.iter().map(|value| format!("{:?}", value)).collect::<Vec<_>>();
+        assert_eq!(constants(actual), constants(expected));
+        assert_eq!(actual.names, expected.names);
+        for offset in 0..=expected.code.len() {
+            let position = |chunk: &Chunk| {
+                chunk
+                    .get_position(offset)
+                    .map(|(line, column)| (line, if compare_columns { column } else { 0 }))
+            };
+            assert_eq!(position(actual), position(expected), "position of offset {}", offset);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use super::testing::{interpret_captured, ClosedPipe, SharedBuffer};
+    use crate::error::VerifierError;
 
     #[test]
     fn should_iterate_with_instruction_pointer() {
@@ -165,4 +650,315 @@ mod tests {
         assert_eq!(ip.next(), 2);
         assert_eq!(ip.next(), 3);
     }
+
+    #[test]
+    fn should_reject_chunk_without_return() {
+        let mut chunk = Chunk::new();
+        chunk.write_chunk(OpCode::OpNil as u8, 1, 1);
+        let mut vm = VirtualMachine::new(false);
+
+        let error = vm.execute(&chunk).unwrap_err();
+        assert!(matches!(error, InterpretError::Verifier(VerifierError::MissingReturn)));
+        assert_eq!(error.to_string(), "Chunk does not end with a return instruction");
+    }
+
+    #[test]
+    fn should_return_value_of_expression() {
+        let mut vm = VirtualMachine::new(false);
+
+        let value = vm.interpret("-(1.5 + 2) * 2").unwrap();
+        assert_eq!(value.number(), Some(-7.0));
+        assert!(vm.interpret("nil").unwrap().is_nil());
+    }
+
+    #[test]
+    fn should_fail_with_typed_errors() {
+        let mut vm = VirtualMachine::new(false);
+
+        match vm.interpret("1 +").unwrap_err() {
+            InterpretError::Compile(error) => {
+                assert_eq!(error.message(), "Expected expression");
+            }
+            error => panic!("Expected a compile error, got {:?}", error),
+        }
+        match vm.interpret("1 +\n  -true").unwrap_err() {
+            InterpretError::Runtime(RuntimeError {
+                kind: ExecutionError::Value(ValueError::NegateOperand(operand)),
+                trace,
+            }) => {
+                assert_eq!(operand.boolean(), Some(true));
+                assert_eq!(trace, vec![Frame { offset: 3, line: 2, column: 4 }]);
+            }
+            error => panic!("Expected a runtime error, got {:?}", error),
+        }
+    }
+
+    #[test]
+    fn should_reset_stack_after_runtime_error() {
+        let mut vm = VirtualMachine::new(false);
+        // leaves three values on the stack when failing
+        let source = (0..STACK_MAX).map(|_| "1 + (2 + (3 * nil))").collect::<Vec<_>>().join(" + ");
+        let chunk = vm.compile(&source).unwrap();
+
+        for _ in 0..STACK_MAX {
+            assert!(matches!(vm.execute(&chunk), Err(InterpretError::Runtime(_))));
+        }
+        assert_eq!(vm.interpret("1 + 2").unwrap().number(), Some(3.0));
+    }
+
+    fn native_vm() -> VirtualMachine {
+        let mut vm = VirtualMachine::new(false);
+        vm.define_native("add", 2, |_, arguments| match (arguments[0].number(), arguments[1].number()) {
+            (Some(lhs), Some(rhs)) => Ok(Value::Number(lhs + rhs)),
+            _ => Err(NativeError::new("operands must be numbers")),
+        });
+        vm.define_native("zero", 0, |_, _| Ok(Value::Number(0.0)));
+        vm
+    }
+
+    #[test]
+    fn should_call_natives() {
+        let mut vm = native_vm();
+
+        assert_eq!(vm.interpret("add(1, add(zero(), 2)) * 2").unwrap().number(), Some(6.0));
+        assert!(vm.stack().is_empty());
+        assert!(vm.global("add").and_then(|add| add.native()).is_some());
+    }
+
+    #[test]
+    fn should_run_after_being_moved() {
+        let machines = vec![native_vm(), native_vm()];
+        let mut vm = machines.into_iter().last().unwrap();
+
+        assert_eq!(vm.interpret("add(1, 2) + 3").unwrap().number(), Some(6.0));
+        assert!(vm.stack().is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn should_panic_instead_of_using_a_replaced_stack() {
+        let mut vm = VirtualMachine::new(false);
+        vm.define_native("swap", 0, |vm, _| {
+            *vm = VirtualMachine::new(false);
+            Ok(Value::Nil)
+        });
+
+        let _ = vm.interpret("1 + swap()");
+    }
+
+    #[test]
+    fn should_report_failing_calls() {
+        let mut vm = native_vm();
+        let error = |vm: &mut VirtualMachine, source| vm.interpret(source).unwrap_err().to_string();
+
+        assert_eq!(error(&mut vm, "add(1)"), "add expects 2 arguments but got 1\n[4] 1:6");
+        assert_eq!(error(&mut vm, "1 +\n add(1, nil)"), "add: operands must be numbers\n[7] 2:12");
+        assert_eq!(error(&mut vm, "true()"), "Can only call functions, not true\n[1] 1:6");
+        assert_eq!(error(&mut vm, "missing(1)"), "Undefined variable 'missing'\n[0] 1:1");
+        assert_eq!(vm.interpret("add(zero(), 1)").unwrap().number(), Some(1.0));
+    }
+
+    #[test]
+    fn should_convert_arguments_of_typed_natives() {
+        let mut vm = VirtualMachine::new(false);
+        vm.define_function("hypot", |x: f64, y: f64| x.hypot(y));
+        vm.define_function("nth", |n: u8, fallback: Option<bool>| -> Result<bool, NativeError> {
+            fallback.ok_or_else(|| NativeError::new(format!("no value for {}", n)))
+        });
+        vm.define_function("nothing", || ());
+
+        assert_eq!(vm.interpret("hypot(3, 4)").unwrap().number(), Some(5.0));
+        assert_eq!(vm.interpret("nth(1, true)").unwrap().boolean(), Some(true));
+        assert!(vm.interpret("nothing()").unwrap().is_nil());
+        let error = |vm: &mut VirtualMachine, source| vm.interpret(source).unwrap_err().to_string();
+        assert_eq!(error(&mut vm, "hypot(3, nil)"), "hypot: argument 2 expected number, got nil\n[5] 1:13");
+        assert_eq!(error(&mut vm, "nth(1.5, nil)"), "nth: argument 1 expected u8, got 1.5\n[5] 1:13");
+        assert_eq!(error(&mut vm, "nth(2, nil)"), "nth: no value for 2\n[5] 1:11");
+        assert_eq!(error(&mut vm, "nothing(1)"), "nothing expects 0 arguments but got 1\n[4] 1:10");
+    }
+
+    #[test]
+    fn should_call_globals_from_host() {
+        let mut vm = native_vm();
+
+        assert_eq!(vm.call("add", &[Value::Number(1.0), Value::Number(2.0)]).unwrap().number(), Some(3.0));
+        let error = |vm: &mut VirtualMachine, name, arguments: &[Value]| vm.call(name, arguments).unwrap_err().to_string();
+        assert_eq!(error(&mut vm, "missing", &[]), "Undefined variable 'missing'");
+        assert_eq!(error(&mut vm, "zero", &[Value::Nil]), "zero expects 0 arguments but got 1");
+        assert_eq!(error(&mut vm, "add", &[Value::Nil, Value::Nil]), "add: operands must be numbers");
+    }
+
+    #[test]
+    fn should_execute_code_from_natives() {
+        let mut vm = native_vm();
+        vm.define_native("twice", 1, |vm, arguments| Ok(vm.call("add", &[arguments[0], arguments[0]])?));
+        vm.define_native("script", 0, |vm, _| Ok(vm.interpret("add(twice(1), 2)")?));
+        vm.define_native("failing", 0, |vm, _| Ok(vm.interpret("1 +\n  -true")?));
+
+        assert_eq!(vm.interpret("1 + twice(script())").unwrap().number(), Some(9.0));
+        assert_eq!(vm.call("script", &[]).unwrap().number(), Some(4.0));
+        assert_eq!(
+            vm.interpret("2 * failing()").unwrap_err().to_string(),
+            "failing: Unable to negate true, operand must be a number\n[3] 2:4\n[4] 1:13"
+        );
+        assert!(vm.stack().is_empty());
+        assert_eq!(vm.interpret("add(1, 2)").unwrap().number(), Some(3.0));
+    }
+
+    #[test]
+    fn should_reject_nested_execution_without_stack_room() {
+        let nested = |depth, innermost| "(1 + ".repeat(depth) + innermost + &")".repeat(depth);
+        let mut vm = native_vm();
+        vm.define_native("deep", 0, move |vm, _| Ok(vm.interpret(&nested(STACK_MAX / 2, "1"))?));
+
+        assert_eq!(vm.interpret("deep()").unwrap().number(), Some((STACK_MAX / 2 + 1) as f64));
+        let error = vm.interpret(&nested(STACK_MAX / 2, "deep()")).unwrap_err();
+        assert!(error.to_string().starts_with("deep: Stack overflow\n[0] 1:2"), "{}", error);
+        assert!(vm.stack().is_empty());
+    }
+
+    fn runtime_error(result: Result<Value, InterpretError>) -> RuntimeError {
+        match result {
+            Err(InterpretError::Runtime(error)) => error,
+            result => panic!("Expected a runtime error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn should_stop_when_out_of_fuel() {
+        let mut vm = VirtualMachine::new(false);
+        vm.set_fuel(Some(3));
+
+        let error = runtime_error(vm.interpret("1 + 2"));
+        assert!(matches!(error.kind, ExecutionError::OutOfFuel));
+        assert_eq!(error.trace[0].offset, 5);
+        assert_eq!(vm.fuel(), Some(0));
+
+        vm.set_fuel(Some(CHECK_INTERVAL + 10));
+        assert_eq!(vm.interpret("1 + 2").unwrap().number(), Some(3.0));
+        assert_eq!(vm.fuel(), Some(CHECK_INTERVAL + 6));
+        vm.set_fuel(None);
+        assert_eq!(vm.interpret("-1").unwrap().number(), Some(-1.0));
+        assert_eq!(vm.fuel(), None);
+    }
+
+    #[test]
+    fn should_run_out_of_fuel_in_nested_execution() {
+        let mut vm = native_vm();
+        vm.define_native("script", 0, |vm, _| Ok(vm.interpret("1 + 2 + 3")?));
+        vm.define_native("indirect", 0, |vm, _| Ok(vm.call("script", &[])?));
+
+        vm.set_fuel(Some(4));
+        let error = runtime_error(vm.interpret("1 + script()"));
+        assert!(matches!(error.kind, ExecutionError::OutOfFuel), "{:?}", error.kind);
+        assert_eq!(
+            error.trace.iter().map(|frame| frame.offset).collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+
+        vm.set_fuel(Some(3));
+        let error = vm.call("indirect", &[]).unwrap_err();
+        assert!(matches!(error.kind, ExecutionError::OutOfFuel), "{:?}", error.kind);
+        assert_eq!(error.trace.len(), 1);
+        assert!(vm.stack().is_empty());
+    }
+
+    #[test]
+    fn should_stop_at_deadline() {
+        let mut vm = VirtualMachine::new(false);
+        vm.set_deadline(Some(Instant::now()));
+
+        let error = runtime_error(vm.interpret("1 + 2"));
+        assert!(matches!(error.kind, ExecutionError::DeadlineExceeded));
+        assert_eq!(error.trace[0].offset, 0);
+
+        vm.set_deadline(Some(Instant::now() + std::time::Duration::from_secs(60)));
+        assert_eq!(vm.interpret("1 + 2").unwrap().number(), Some(3.0));
+    }
+
+    #[test]
+    fn should_stop_when_interrupted() {
+        let mut vm = VirtualMachine::new(false);
+        let handle = vm.interrupt_handle();
+        vm.define_native("interrupt", 0, move |_, _| {
+            let handle = handle.clone();
+            std::thread::spawn(move || handle.interrupt()).join().unwrap();
+            Ok(Value::Number(0.0))
+        });
+        let additions = "1 + ".repeat(CHECK_INTERVAL as usize);
+
+        let error = runtime_error(vm.interpret(&format!("interrupt() + {}1", additions)));
+        assert!(matches!(error.kind, ExecutionError::Interrupted));
+        assert!(vm.stack().is_empty());
+
+        // Interrupts outside of executions are dropped
+        vm.interrupt_handle().interrupt();
+        assert_eq!(vm.interpret("1 + 2").unwrap().number(), Some(3.0));
+    }
+
+    #[test]
+    fn should_print_results_to_output() {
+        assert_eq!(interpret_captured("1 + 2").unwrap(), "3\n");
+        assert_eq!(
+            interpret_captured("-nil").unwrap_err().to_string(),
+            "Unable to negate nil, operand must be a number\n[1] 1:2"
+        );
+    }
+
+    #[test]
+    fn should_print_only_results_of_top_level_interpret() {
+        let output = SharedBuffer::default();
+        let mut vm = VmConfig::new().output(output.clone()).build();
+        vm.define_native("script", 0, |vm, _| Ok(vm.interpret("1 + 1")?));
+
+        vm.interpret("script() + 1").unwrap();
+        vm.call("script", &[]).unwrap();
+        let chunk = vm.compile("4").unwrap();
+        vm.execute(&chunk).unwrap();
+        assert_eq!(output.contents(), "3\n");
+    }
+
+    #[test]
+    fn should_locate_runtime_errors_in_frames() {
+        let mut vm = native_vm();
+
+        let error = runtime_error(vm.interpret("1 +\n  add(1, nil)"));
+        assert_eq!(error.trace, vec![Frame { offset: 7, line: 2, column: 13 }]);
+        let error = vm.call("add", &[Value::Nil, Value::Nil]).unwrap_err();
+        assert!(matches!(error.kind, ExecutionError::Native { .. }));
+        assert!(error.trace.is_empty());
+    }
+
+    #[test]
+    fn should_report_failing_output() {
+        let mut vm = VmConfig::new().output(ClosedPipe::default()).build();
+        let error = vm.interpret("1").unwrap_err();
+        assert!(matches!(&error, InterpretError::Output(error) if error.kind() == std::io::ErrorKind::BrokenPipe));
+
+        let mut vm = VmConfig::new().debug(true).debug_output(ClosedPipe::default()).build();
+        assert!(matches!(vm.compile("1"), Err(InterpretError::Output(_))));
+    }
+
+    #[test]
+    fn should_print_bytecode_to_debug_output() {
+        let output = SharedBuffer::default();
+        let debug_output = SharedBuffer::default();
+        let mut vm = VmConfig::new()
+            .debug(true)
+            .output(output.clone())
+            .debug_output(debug_output.clone())
+            .build();
+
+        vm.interpret("true").unwrap();
+        assert_eq!(output.contents(), "true\n");
+        assert_eq!(debug_output.contents(), "== code ==\n0000    1 OP_TRUE\n0001    | OP_RETURN\n== code ==\n");
+    }
+
+    #[test]
+    fn should_read_long_operand_with_instruction_pointer() {
+        let data = vec![0x01, 0x02, 0x03];
+        let mut ip = InstructionPointer::new(&data);
+
+        assert_eq!(ip.next_long(), 0x030201);
+    }
 }
@@ -0,0 +1,60 @@
+use std::io::{self, Write};
+
+use crate::tracer::{NoTracer, Tracer};
+
+use super::VirtualMachine;
+
+/// Builds a [`VirtualMachine`] whose output goes elsewhere than stdout.
+///
+/// ```
+/// # use fast_frox::virtual_machine::VmConfig;
+/// let mut vm = VmConfig::new().output(Vec::new()).build();
+/// vm.interpret("1 + 2").unwrap();
+/// ```
+pub struct VmConfig {
+    pub(super) debug: bool,
+    pub(super) output: Box<dyn Write>,
+    pub(super) debug_output: Box<dyn Write>,
+}
+
+impl Default for VmConfig {
+    fn default() -> Self {
+        VmConfig {
+            debug: false,
+            output: Box::new(io::stdout()),
+            debug_output: Box::new(io::stdout()),
+        }
+    }
+}
+
+impl VmConfig {
+    pub fn new() -> Self {
+        VmConfig::default()
+    }
+
+    /// Prints the bytecode of every compiled chunk to the debug output.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Receives the results of [`VirtualMachine::interpret`], which is what scripts print.
+    pub fn output(mut self, output: impl Write + 'static) -> Self {
+        self.output = Box::new(output);
+        self
+    }
+
+    /// Receives the bytecode printed in debug mode.
+    pub fn debug_output(mut self, debug_output: impl Write + 'static) -> Self {
+        self.debug_output = Box::new(debug_output);
+        self
+    }
+
+    pub fn build(self) -> VirtualMachine {
+        self.build_with_tracer(NoTracer)
+    }
+
+    pub fn build_with_tracer<T: Tracer>(self, tracer: T) -> VirtualMachine<T> {
+        VirtualMachine::from_config(self, tracer)
+    }
+}